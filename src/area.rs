@@ -0,0 +1,293 @@
+use std::str::FromStr;
+
+use geo::{Centroid, Intersects, MultiPolygon, Point};
+
+use crate::{compute_distance, Route, Target, TargetDistance};
+
+/// Region to search photos in
+#[derive(Debug, Clone)]
+pub enum Area {
+    /// Within `radius` meters of `center`
+    Circle {
+        center: (f64, f64),
+        radius: f64,
+    },
+    BoundingBox(BoundingBox),
+    /// Inside any of the polygons and outside their holes. Coordinates are
+    /// `x` longitude and `y` latitude as in GeoJSON.
+    Polygon(MultiPolygon<f64>),
+    /// Within `radius` meters of any of the targets
+    Targets {
+        targets: Vec<Target>,
+        radius: f64,
+    },
+    /// Within `radius` meters of any point of a track or route
+    Corridor {
+        route: Route,
+        radius: f64,
+    },
+}
+
+impl Area {
+    /// Reference point for distances reported and sorted by
+    pub fn center(&self) -> (f64, f64) {
+        match self {
+            Area::Circle { center, .. } => *center,
+            Area::BoundingBox(bbox) => bbox.center(),
+            Area::Polygon(polygons) => polygons
+                .centroid()
+                .map_or((0.0, 0.0), |point| (point.y(), point.x())),
+            Area::Targets { targets, .. } => {
+                targets.first().map_or((0.0, 0.0), |target| target.coords)
+            }
+            Area::Corridor { route, .. } => route.start().unwrap_or((0.0, 0.0)),
+        }
+    }
+
+    /// Meters from `center()`, or from the nearest target or point of a
+    /// route
+    pub fn distance(&self, coords: (f64, f64)) -> f64 {
+        match self {
+            Area::Targets { targets, .. } => targets
+                .iter()
+                .map(|target| compute_distance(target.coords, coords))
+                .fold(f64::INFINITY, f64::min),
+            Area::Corridor { route, .. } => route.locate(coords).0,
+            _ => compute_distance(self.center(), coords),
+        }
+    }
+
+    /// Meters along the route to the point nearest to coordinates
+    pub fn route_position(&self, coords: (f64, f64)) -> Option<f64> {
+        match self {
+            Area::Corridor { route, .. } => Some(route.locate(coords).1),
+            _ => None,
+        }
+    }
+
+    /// Distance to each target when searching around several. Each target
+    /// matches within its own annulus from `min_distance` to the radius.
+    pub fn target_distances(&self, coords: (f64, f64), min_distance: f64) -> Vec<TargetDistance> {
+        match self {
+            Area::Targets { targets, radius } => targets
+                .iter()
+                .map(|target| {
+                    let distance = compute_distance(target.coords, coords);
+                    TargetDistance {
+                        name: target.name.clone(),
+                        distance,
+                        matched: min_distance <= distance && distance <= *radius,
+                    }
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether coordinates are in the area, given their `distance()`
+    pub fn contains(&self, coords: (f64, f64), distance: f64) -> bool {
+        match self {
+            Area::Circle { radius, .. }
+            | Area::Targets { radius, .. }
+            | Area::Corridor { radius, .. } => distance <= *radius,
+            Area::BoundingBox(bbox) => bbox.contains(coords),
+            // Points on the boundary count as inside
+            Area::Polygon(polygons) => polygons.intersects(&Point::new(coords.1, coords.0)),
+        }
+    }
+}
+
+/// Rectangle in degrees as used by OSM and Nominatim. `min_lon` greater than
+/// `max_lon` means the box crosses the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    /// Check that coordinates are in range and `min_lat` is not above
+    /// `max_lat`
+    pub fn new(
+        min_lon: f64,
+        min_lat: f64,
+        max_lon: f64,
+        max_lat: f64,
+    ) -> Result<BoundingBox, String> {
+        let lons_valid = [min_lon, max_lon]
+            .iter()
+            .all(|lon| (-180.0..=180.0).contains(lon));
+        let lats_valid = [min_lat, max_lat]
+            .iter()
+            .all(|lat| (-90.0..=90.0).contains(lat));
+        if !lons_valid || !lats_valid || min_lat > max_lat {
+            return Err(format!(
+                "Invalid bounding box {},{},{},{}",
+                min_lon, min_lat, max_lon, max_lat
+            ));
+        }
+        Ok(BoundingBox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.min_lon > self.max_lon
+    }
+
+    pub fn contains(&self, (lat, lon): (f64, f64)) -> bool {
+        let lat_inside = self.min_lat <= lat && lat <= self.max_lat;
+        let lon_inside = if self.crosses_antimeridian() {
+            self.min_lon <= lon || lon <= self.max_lon
+        } else {
+            self.min_lon <= lon && lon <= self.max_lon
+        };
+        lat_inside && lon_inside
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        let lat = (self.min_lat + self.max_lat) / 2.0;
+        let lon = if self.crosses_antimeridian() {
+            let lon = (self.min_lon + self.max_lon + 360.0) / 2.0;
+            if lon > 180.0 {
+                lon - 360.0
+            } else {
+                lon
+            }
+        } else {
+            (self.min_lon + self.max_lon) / 2.0
+        };
+        (lat, lon)
+    }
+}
+
+/// Parse `min_lon,min_lat,max_lon,max_lat`
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid bounding box {}: {}", s, e))?;
+        match values[..] {
+            [min_lon, min_lat, max_lon, max_lat] => {
+                BoundingBox::new(min_lon, min_lat, max_lon, max_lat)
+            }
+            _ => Err(format!("Expected min_lon,min_lat,max_lon,max_lat: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bbox() {
+        let bbox: BoundingBox = "-108.93,45.46,-107.46,46.50".parse().unwrap();
+
+        assert_eq!(
+            bbox,
+            BoundingBox {
+                min_lon: -108.93,
+                min_lat: 45.46,
+                max_lon: -107.46,
+                max_lat: 46.50
+            }
+        );
+        assert!("1,2,3".parse::<BoundingBox>().is_err());
+        assert!("a,2,3,4".parse::<BoundingBox>().is_err());
+        assert!("0,50,1,40".parse::<BoundingBox>().is_err());
+        assert!("0,0,190,1".parse::<BoundingBox>().is_err());
+    }
+
+    #[test]
+    fn bbox_contains() {
+        let bbox: BoundingBox = "-108.93,45.46,-107.46,46.50".parse().unwrap();
+
+        assert!(bbox.contains((45.96, -108.28)));
+        assert!(!bbox.contains((44.0, -108.28)));
+        assert!(!bbox.contains((45.96, -100.0)));
+        assert_eq!(
+            bbox.center(),
+            ((45.46 + 46.50) / 2.0, (-108.93 - 107.46) / 2.0)
+        );
+    }
+
+    #[test]
+    fn bbox_across_antimeridian() {
+        // Fiji
+        let bbox: BoundingBox = "177.0,-19.5,-178.0,-15.5".parse().unwrap();
+
+        assert!(bbox.crosses_antimeridian());
+        assert!(bbox.contains((-17.7, 178.0)));
+        assert!(bbox.contains((-17.7, -179.5)));
+        assert!(!bbox.contains((-17.7, 0.0)));
+        assert!(!bbox.contains((-17.7, 170.0)));
+        assert_eq!(bbox.center(), (-17.5, 179.5));
+
+        let bbox: BoundingBox = "178.0,0,-170.0,1".parse().unwrap();
+        assert_eq!(bbox.center().1, -176.0);
+    }
+
+    #[test]
+    fn area_contains() {
+        let circle = Area::Circle {
+            center: (0.0, 0.0),
+            radius: 100.0,
+        };
+
+        assert!(circle.contains((0.0, 0.0), 99.0));
+        assert!(!circle.contains((0.0, 0.0), 101.0));
+
+        let square = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        let hole = vec![(4.0, 4.0), (6.0, 4.0), (6.0, 6.0), (4.0, 6.0)];
+        let polygon = Area::Polygon(MultiPolygon::new(vec![geo::Polygon::new(
+            square.into(),
+            vec![hole.into()],
+        )]));
+
+        assert!(polygon.contains((2.0, 8.0), 0.0));
+        assert!(polygon.contains((0.0, 3.0), 0.0));
+        assert!(!polygon.contains((5.0, 5.0), 0.0));
+        assert!(!polygon.contains((2.0, 11.0), 0.0));
+        assert_eq!(polygon.center(), (5.0, 5.0));
+
+        let corridor = Area::Corridor {
+            route: Route::new(vec![(0.0, 0.0), (0.0, 0.01)]),
+            radius: 200.0,
+        };
+        let distance = corridor.distance((0.001, 0.005));
+
+        assert!((distance - 111.2).abs() < 1.0);
+        assert!(corridor.contains((0.001, 0.005), distance));
+        assert!((corridor.route_position((0.001, 0.005)).unwrap() - 556.0).abs() < 1.0);
+        assert_eq!(polygon.route_position((2.0, 8.0)), None);
+
+        let targets = Area::Targets {
+            targets: vec![Target::at((0.0, 0.0)), Target::at((0.0, 0.01))],
+            radius: 500.0,
+        };
+        let distance = targets.distance((0.0, 0.008));
+        let distances = targets.target_distances((0.0, 0.008), 0.0);
+
+        assert!((distance - 222.4).abs() < 1.0);
+        assert!(targets.contains((0.0, 0.008), distance));
+        assert_eq!(distances.len(), 2);
+        assert!(!distances[0].matched);
+        assert!(distances[1].matched);
+        assert_eq!(distances[1].name, "0,0.01");
+
+        // About 445 m from the first target, in its annulus only
+        let distances = targets.target_distances((0.0, 0.004), 300.0);
+        assert!(distances[0].matched);
+        assert!(!distances[1].matched);
+    }
+}
@@ -1,73 +1,364 @@
 use std::{
-    fs::{self, File},
-    io::BufWriter,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
-use sanitize_filename::sanitize;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 
-// FIXME: implement evict
+use crate::{Error, Result};
+
+mod dir_store;
+mod log_store;
+mod memory_store;
+
+pub use dir_store::DirStore;
+pub use log_store::LogStore;
+pub use memory_store::MemoryStore;
+
+/// Uses closer together than this are not recorded, so that searching the
+/// same photos again does not write to the store
+const USE_PRECISION: Duration = Duration::from_secs(24 * 3600);
+
+/// Where a cache keeps its entries. Contents are JSON text.
+pub trait Store: Send + Sync {
+    /// Path shown in errors about an entry
+    fn location(&self, key: &str) -> PathBuf;
+    /// Contents of an entry, `None` if it was never written
+    fn get(&self, key: &str) -> Result<Option<String>>;
+    /// Write an entry, in place of an existing one only if `replace`
+    fn insert(&self, key: &str, contents: &str, replace: bool) -> Result<()>;
+    /// Record when an entry was last used, unless it was within
+    /// `USE_PRECISION` before
+    fn touch(&self, key: &str, used: SystemTime) -> Result<()>;
+    fn remove(&self, key: &str) -> Result<()>;
+    /// Every entry with its size and last use, in no particular order
+    fn entries(&self) -> Result<Vec<StoreEntry>>;
+    /// Write what is only kept in memory, such as uses
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Give back the space of removed entries
+    fn compact(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Whether replaced and removed entries take more space than the live
+    /// ones, so that `compact` is worth it
+    fn should_compact(&self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreEntry {
+    pub key: String,
+    pub bytes: u64,
+    pub used: SystemTime,
+}
+
+/// JSON entries by key. Entries are touched when read, so that the least
+/// recently used can be pruned first.
 pub struct Cache {
-    path: PathBuf,
+    store: Box<dyn Store>,
+    limits: CacheLimits,
+}
+
+/// Bounds to prune a cache to, unlimited by default
+#[derive(Debug, Clone, Default)]
+pub struct CacheLimits {
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<u64>,
+    /// Remove entries unused for longer than this
+    pub max_age: Option<Duration>,
+}
+
+impl CacheLimits {
+    fn exceeded_by(&self, entries: &[StoreEntry]) -> bool {
+        let now = SystemTime::now();
+        self.max_entries
+            .is_some_and(|max_entries| entries.len() > max_entries)
+            || self.max_bytes.is_some_and(|max_bytes| {
+                entries.iter().map(|entry| entry.bytes).sum::<u64>() > max_bytes
+            })
+            || self.max_age.is_some_and(|max_age| {
+                entries
+                    .iter()
+                    .any(|entry| now.duration_since(entry.used).unwrap_or_default() > max_age)
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PruneReason {
+    /// The entry is about a file that no longer exists
+    Orphan,
+    /// Unused for longer than the max age
+    Expired,
+    /// Least recently used beyond the max entry count
+    OverCount,
+    /// Least recently used beyond the max total size
+    OverSize,
+}
+
+/// Entry removed by `Cache::prune`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pruned {
+    pub key: String,
+    pub bytes: u64,
+    pub reason: PruneReason,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PruneReport {
+    pub removed: Vec<Pruned>,
+    pub kept_entries: usize,
+    pub kept_bytes: u64,
 }
 
 impl Cache {
-    pub fn new(path: &Path) -> Cache {
-        fs::create_dir_all(&path).expect("Error creating cache dir");
+    /// Cache of one JSON file per entry in a directory
+    pub fn new(path: &Path) -> Result<Cache> {
+        Ok(Cache::with_store(DirStore::new(path)?))
+    }
+
+    /// Cache of all entries in one file, which is faster to open than
+    /// a directory of many small files, above all on network drives
+    pub fn single_file(path: &Path) -> Result<Cache> {
+        Ok(Cache::with_store(LogStore::new(path)?))
+    }
+
+    /// Cache that is lost when dropped
+    pub fn in_memory() -> Cache {
+        Cache::with_store(MemoryStore::new())
+    }
+
+    pub fn with_store(store: impl Store + 'static) -> Cache {
         Cache {
-            path: path.to_path_buf(),
+            store: Box::new(store),
+            limits: CacheLimits::default(),
         }
     }
 
-    pub fn write(&self, key: &str, json: Value) {
-        // FIXME: sanitize key for filename
-        let path = self.path.join(sanitize(key));
-        if !path.exists() {
-            let file = File::create(path).unwrap();
-            let mut writer = BufWriter::new(file);
-            serde_json::to_writer(&mut writer, &json).unwrap();
-        } else {
-            panic!("Cache file exists: {}", path.to_string_lossy());
-        }
+    /// Bounds that `evict` keeps the cache within
+    pub fn limits(mut self, limits: CacheLimits) -> Cache {
+        self.limits = limits;
+        self
     }
 
-    pub fn read(&self, key: &str) -> Option<Value> {
-        let path = self.path.join(sanitize(key));
-        if path.exists() {
-            let contents = fs::read_to_string(path).unwrap();
-            let json: Value = serde_json::from_str(&contents).unwrap();
-            Some(json)
-        } else {
-            None
+    pub fn write(&self, key: &str, json: Value) -> Result<()> {
+        self.write_from(key, json)
+    }
+
+    pub fn read(&self, key: &str) -> Result<Option<Value>> {
+        self.read_into(key)
+    }
+
+    /// Write an entry unless one exists. Concurrent readers never see a
+    /// partial entry and concurrent writers of the same key keep the first
+    /// one.
+    pub fn write_from(&self, key: &str, value: impl Serialize) -> Result<()> {
+        let contents = self.to_json(key, value)?;
+        self.store.insert(key, &contents, false)
+    }
+
+    /// Write an entry in place of an outdated one, as atomically as
+    /// `write_from`
+    pub fn replace_from(&self, key: &str, value: impl Serialize) -> Result<()> {
+        let contents = self.to_json(key, value)?;
+        self.store.insert(key, &contents, true)
+    }
+
+    fn to_json(&self, key: &str, value: impl Serialize) -> Result<String> {
+        serde_json::to_string(&value).map_err(|source| Error::Json {
+            path: self.store.location(key),
+            source,
+        })
+    }
+
+    /// Entry of a key, `None` if it was never written
+    pub fn read_into<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let Some(contents) = self.store.get(key)? else {
+            return Ok(None);
+        };
+        // Only slows down eviction of this entry, so failures are ignored
+        let _ = self.store.touch(key, SystemTime::now());
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|source| Error::Json {
+                path: self.store.location(key),
+                source,
+            })
+    }
+
+    /// Write what the store only keeps in memory, such as uses, which is
+    /// otherwise done when the cache is dropped without reporting errors
+    pub fn flush(&self) -> Result<()> {
+        self.store.flush()
+    }
+
+    /// Copy the entries of a directory cache, as written by `Cache::new`,
+    /// keeping entries of the same key in this cache. Returns how many
+    /// entries were copied.
+    ///
+    /// Entries are keyed by their file name. Names cut at the length limit
+    /// are skipped, while keys that lost characters invalid in file names,
+    /// such as `:` or `?`, cannot be told apart and are never found again
+    /// until they are pruned.
+    pub fn import(&self, dir: &Path) -> Result<usize> {
+        let from = DirStore::new(dir)?;
+        let mut imported = 0;
+        for entry in from.entries()? {
+            if !DirStore::is_whole_key(&entry.key) || self.store.get(&entry.key)?.is_some() {
+                continue;
+            }
+            if let Some(contents) = from.get(&entry.key)? {
+                self.store.insert(&entry.key, &contents, false)?;
+                self.store.touch(&entry.key, entry.used)?;
+                imported += 1;
+            }
         }
+        self.store.flush()?;
+        Ok(imported)
     }
 
-    pub fn write_from(&self, key: &str, value: impl Serialize) {
-        let path = self.path.join(sanitize(key));
-        if !path.exists() {
-            let ser = serde_json::to_string(&value).unwrap();
-            fs::write(path, ser).unwrap();
-        } else {
-            panic!("Cache file exists: {}", path.to_string_lossy());
+    /// Prune the cache to its limits if it is over them, as after a search,
+    /// or else compact a store that holds mostly replaced entries. Returns
+    /// what was pruned, if anything.
+    pub fn evict(&self) -> Result<Option<PruneReport>> {
+        let limited = self.limits.max_entries.is_some()
+            || self.limits.max_bytes.is_some()
+            || self.limits.max_age.is_some();
+        if limited && self.limits.exceeded_by(&self.store.entries()?) {
+            return self.prune(&self.limits, None).map(Some);
+        }
+        if self.store.should_compact()? {
+            self.store.compact()?;
         }
+        Ok(None)
     }
 
-    pub fn read_into<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
-        let path = self.path.join(sanitize(key));
-        if path.exists() {
-            let contents = fs::read_to_string(path).unwrap();
-            let des: T = serde_json::from_str(&contents).unwrap();
-            Some(des)
-        } else {
-            None
+    /// Remove orphaned entries, then expired ones, then the least recently
+    /// used until the cache is within limits. Entries are only read to tell
+    /// orphans with `is_orphan`, and those that are not valid JSON are never
+    /// orphans.
+    pub fn prune(
+        &self,
+        limits: &CacheLimits,
+        is_orphan: Option<&dyn Fn(&Value) -> bool>,
+    ) -> Result<PruneReport> {
+        let mut entries = self.store.entries()?;
+        // Most recently used first
+        entries.sort_by(|a, b| b.used.cmp(&a.used).then_with(|| a.key.cmp(&b.key)));
+
+        let now = SystemTime::now();
+        let mut report = PruneReport::default();
+        for entry in entries {
+            let orphan = |is_orphan: &dyn Fn(&Value) -> bool| {
+                self.store
+                    .get(&entry.key)
+                    .ok()
+                    .flatten()
+                    .and_then(|contents| serde_json::from_str(&contents).ok())
+                    .is_some_and(|value| is_orphan(&value))
+            };
+            let reason = if is_orphan.is_some_and(orphan) {
+                Some(PruneReason::Orphan)
+            } else if limits
+                .max_age
+                .is_some_and(|max_age| now.duration_since(entry.used).unwrap_or_default() > max_age)
+            {
+                Some(PruneReason::Expired)
+            } else if limits
+                .max_entries
+                .is_some_and(|max_entries| report.kept_entries >= max_entries)
+            {
+                Some(PruneReason::OverCount)
+            } else if limits
+                .max_bytes
+                .is_some_and(|max_bytes| report.kept_bytes + entry.bytes > max_bytes)
+            {
+                Some(PruneReason::OverSize)
+            } else {
+                None
+            };
+
+            match reason {
+                Some(reason) => {
+                    self.store.remove(&entry.key)?;
+                    report.removed.push(Pruned {
+                        key: entry.key,
+                        bytes: entry.bytes,
+                        reason,
+                    });
+                }
+                None => {
+                    report.kept_entries += 1;
+                    report.kept_bytes += entry.bytes;
+                }
+            }
         }
+        self.store.compact()?;
+        Ok(report)
     }
 }
 
+/// Parse sizes such as `500MB` or `2GiB`, with bare numbers in bytes
+pub fn parse_size(text: &str) -> std::result::Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (value, unit) = text.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("Invalid size: {}", text))?;
+    let factor: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000 * 1000,
+        "gb" => 1000 * 1000 * 1000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "Invalid size {}, expected B, KB, MB, GB, KiB, MiB or GiB",
+                text
+            ))
+        }
+    };
+    value
+        .checked_mul(factor)
+        .ok_or_else(|| format!("Size too large: {}", text))
+}
+
+/// Parse ages such as `90s`, `30min`, `12h` or `90d`
+pub fn parse_age(text: &str) -> std::result::Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (value, unit) = text.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("Invalid age: {}", text))?;
+    let factor: u64 = match unit.trim() {
+        "s" => 1,
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Invalid age {}, expected s, min, h or d", text)),
+    };
+    value
+        .checked_mul(factor)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Age too long: {}", text))
+}
+
 #[cfg(test)]
 mod tests {
+    use std::fs;
+
     use super::*;
     use serde::{Deserialize, Serialize};
     use tempfile::tempdir;
@@ -84,16 +375,267 @@ mod tests {
         };
         let dir = tempdir().unwrap();
         let cache_path = dir.path();
-        let cache = Cache::new(cache_path);
+        let cache = Cache::new(cache_path).unwrap();
         let key = "test-key";
 
-        cache.write_from(key, &data);
+        cache.write_from(key, &data).unwrap();
 
         let saved_str = fs::read_to_string(cache_path.join(key)).unwrap();
         assert_eq!(saved_str, r#"{"coordinates":[1.2,3.4]}"#);
 
-        let des: Data = cache.read_into(key).unwrap();
+        let des: Data = cache.read_into(key).unwrap().unwrap();
 
         assert_eq!(data.coordinates, des.coordinates);
     }
+
+    #[test]
+    fn write_concurrently() {
+        let dir = tempdir().unwrap();
+        let cache = Cache::new(dir.path()).unwrap();
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let cache = &cache;
+                scope.spawn(move || {
+                    for key in ["a", "b", "c"] {
+                        cache
+                            .write_from(
+                                key,
+                                Data {
+                                    coordinates: Some((i as f64, 0.0)),
+                                },
+                            )
+                            .unwrap();
+                        let des: Data = cache.read_into(key).unwrap().unwrap();
+                        assert!(des.coordinates.is_some());
+                    }
+                });
+            }
+        });
+
+        // Only the entries, no leftover temporary files
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn write_any_key() {
+        let dir = tempdir().unwrap();
+        let long = |end| format!("{}{}", "x".repeat(300), end);
+        let caches = [
+            Cache::new(&dir.path().join("dir")).unwrap(),
+            Cache::single_file(&dir.path().join("cache.log")).unwrap(),
+            Cache::in_memory(),
+        ];
+        for cache in caches.iter() {
+            cache.write("a/b:c", serde_json::json!(1)).unwrap();
+            assert_eq!(cache.read("a/b:c").unwrap().unwrap(), 1);
+            cache.write(&long("a"), serde_json::json!(2)).unwrap();
+            cache.write(&long("b"), serde_json::json!(3)).unwrap();
+        }
+
+        // Only file names are cut short
+        assert_eq!(caches[0].read(&long("b")).unwrap().unwrap(), 2);
+        assert_eq!(caches[1].read(&long("b")).unwrap().unwrap(), 3);
+        assert_eq!(caches[2].read(&long("b")).unwrap().unwrap(), 3);
+        let keys: Vec<_> = caches[1]
+            .store
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect();
+        assert!(keys.contains(&"a/b:c".to_string()));
+    }
+
+    #[test]
+    fn replace_entry() {
+        let dir = tempdir().unwrap();
+        let cache = Cache::new(dir.path()).unwrap();
+        let data = |lat| Data {
+            coordinates: Some((lat, 0.0)),
+        };
+
+        cache.write_from("key", data(1.0)).unwrap();
+        cache.write_from("key", data(2.0)).unwrap();
+        let des: Data = cache.read_into("key").unwrap().unwrap();
+        assert_eq!(des.coordinates, Some((1.0, 0.0)));
+
+        cache.replace_from("key", data(3.0)).unwrap();
+        let des: Data = cache.read_into("key").unwrap().unwrap();
+        assert_eq!(des.coordinates, Some((3.0, 0.0)));
+    }
+
+    #[test]
+    fn prune_to_limits() {
+        let dir = tempdir().unwrap();
+        let cache = Cache::new(dir.path()).unwrap();
+        let day = Duration::from_secs(24 * 3600);
+        for (i, key) in ["a", "b", "c", "d", "orphan"].iter().enumerate() {
+            cache
+                .write(key, serde_json::json!({ "orphan": *key == "orphan" }))
+                .unwrap();
+            // a was used most recently, d the longest ago
+            let file = fs::File::options()
+                .write(true)
+                .open(dir.path().join(key))
+                .unwrap();
+            file.set_modified(SystemTime::now() - day * i as u32)
+                .unwrap();
+        }
+        let entry_bytes = fs::metadata(dir.path().join("a")).unwrap().len();
+
+        let limits = CacheLimits {
+            max_entries: Some(2),
+            max_bytes: Some(entry_bytes),
+            max_age: Some(day * 5 / 2),
+        };
+        let report = cache
+            .prune(&limits, Some(&|value| value["orphan"] == true))
+            .unwrap();
+
+        let removed: Vec<_> = report
+            .removed
+            .iter()
+            .map(|pruned| (pruned.key.as_str(), pruned.reason))
+            .collect();
+        assert_eq!(
+            removed,
+            [
+                ("b", PruneReason::OverSize),
+                ("c", PruneReason::OverSize),
+                ("d", PruneReason::Expired),
+                ("orphan", PruneReason::Orphan),
+            ]
+        );
+        assert_eq!((report.kept_entries, report.kept_bytes), (1, entry_bytes));
+        assert!(cache.read("a").unwrap().is_some());
+        assert!(cache.read("b").unwrap().is_none());
+
+        // Reading a entry makes it the most recently used
+        cache.write("e", serde_json::json!({})).unwrap();
+        for key in ["a", "e"] {
+            let file = fs::File::options()
+                .write(true)
+                .open(dir.path().join(key))
+                .unwrap();
+            file.set_modified(SystemTime::now() - day * 2).unwrap();
+        }
+        cache.read("a").unwrap();
+        // Uses within a day of the last are not written
+        let modified = || {
+            fs::metadata(dir.path().join("a"))
+                .and_then(|metadata| metadata.modified())
+                .unwrap()
+        };
+        let used = modified();
+        cache.read("a").unwrap();
+        assert_eq!(modified(), used);
+        let limits = CacheLimits {
+            max_entries: Some(1),
+            ..CacheLimits::default()
+        };
+        let report = cache.prune(&limits, None).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.removed[0].key, "e");
+        assert_eq!(report.removed[0].reason, PruneReason::OverCount);
+    }
+
+    #[test]
+    fn evict_after_use() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.log");
+        let limits = CacheLimits {
+            max_entries: Some(2),
+            ..CacheLimits::default()
+        };
+        let cache = Cache::single_file(&path).unwrap().limits(limits);
+        for key in ["a", "b"] {
+            cache.write(key, serde_json::json!(key)).unwrap();
+        }
+        assert!(cache.evict().unwrap().is_none());
+        cache.write("c", serde_json::json!("c")).unwrap();
+        let report = cache.evict().unwrap().unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.kept_entries, 2);
+        let kept = if report.removed[0].key == "c" {
+            "b"
+        } else {
+            "c"
+        };
+
+        // Replaced entries are given back once they outweigh live ones
+        cache.replace_from(kept, "aa").unwrap();
+        let before = fs::metadata(&path).unwrap().len();
+        assert!(cache.evict().unwrap().is_none());
+        assert_eq!(fs::metadata(&path).unwrap().len(), before);
+        for _ in 0..4 {
+            cache.replace_from(kept, "aa").unwrap();
+        }
+        let before = fs::metadata(&path).unwrap().len();
+        cache.evict().unwrap();
+        assert!(fs::metadata(&path).unwrap().len() < before);
+        assert_eq!(cache.read(kept).unwrap().unwrap(), "aa");
+    }
+
+    #[test]
+    fn import_dir_cache() {
+        let dir = tempdir().unwrap();
+        let old = Cache::new(&dir.path().join("exif")).unwrap();
+        old.write("a.jpg", serde_json::json!({ "n": 1 })).unwrap();
+        old.write("b.jpg", serde_json::json!({ "n": 2 })).unwrap();
+        // Cut to the length limit of file names
+        old.write(&"x".repeat(300), serde_json::json!({ "n": 4 }))
+            .unwrap();
+        let long_ago = SystemTime::now() - Duration::from_secs(10 * 24 * 3600);
+        let file = fs::File::options()
+            .write(true)
+            .open(dir.path().join("exif").join("a.jpg"))
+            .unwrap();
+        file.set_modified(long_ago).unwrap();
+
+        let cache = Cache::single_file(&dir.path().join("exif.log")).unwrap();
+        cache.write("b.jpg", serde_json::json!({ "n": 3 })).unwrap();
+        assert_eq!(cache.import(&dir.path().join("exif")).unwrap(), 1);
+        assert_eq!(cache.read("b.jpg").unwrap().unwrap()["n"], 3);
+
+        // Imported entries keep when they were last used
+        let limits = CacheLimits {
+            max_age: Some(Duration::from_secs(5 * 24 * 3600)),
+            ..CacheLimits::default()
+        };
+        let report = cache.prune(&limits, None).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.removed[0].reason, PruneReason::Expired);
+        assert_eq!(report.kept_entries, 1);
+        assert!(cache.read("a.jpg").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("500MB"), Ok(500_000_000));
+        assert_eq!(parse_size("2 GiB"), Ok(2 << 30));
+        assert!(parse_size("5 parsecs").is_err());
+        assert!(parse_size("-5MB").is_err());
+    }
+
+    #[test]
+    fn parse_ages() {
+        assert_eq!(parse_age("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_age("30min"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_age("90d"), Ok(Duration::from_secs(90 * 24 * 3600)));
+        assert!(parse_age("3 weeks").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("999999999999999999d").is_err());
+    }
+
+    #[test]
+    fn read_missing_and_broken() {
+        let dir = tempdir().unwrap();
+        let cache = Cache::new(dir.path()).unwrap();
+        fs::write(dir.path().join("broken"), "{").unwrap();
+
+        assert!(cache.read("missing").unwrap().is_none());
+        assert!(matches!(cache.read("broken"), Err(Error::Json { .. })));
+    }
 }
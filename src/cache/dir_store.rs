@@ -0,0 +1,134 @@
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sanitize_filename::sanitize;
+use tempfile::NamedTempFile;
+
+use super::{Store, StoreEntry, USE_PRECISION};
+use crate::{Error, Result};
+
+const MAX_NAME_BYTES: usize = 255;
+
+/// One JSON file per entry, named after its key made a valid file name.
+/// The modification time of a file tells when its entry was last used.
+pub struct DirStore {
+    path: PathBuf,
+}
+
+impl DirStore {
+    pub fn new(path: &Path) -> Result<DirStore> {
+        fs::create_dir_all(path).map_err(Error::io(path))?;
+        Ok(DirStore {
+            path: path.to_path_buf(),
+        })
+    }
+
+    // Keys that differ only in characters invalid in file names, or beyond
+    // 255 bytes, share an entry
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.path.join(sanitize(key))
+    }
+
+    /// Whether an entry's name may be its whole key, rather than one cut at
+    /// the length limit of file names
+    pub(super) fn is_whole_key(name: &str) -> bool {
+        name.len() < MAX_NAME_BYTES && sanitize(name) == name
+    }
+
+    // Written to a temporary file and moved into place, so concurrent
+    // readers never see a partial entry
+    fn write_temp(&self, path: &Path, contents: &str) -> Result<NamedTempFile> {
+        let mut file = NamedTempFile::new_in(&self.path).map_err(Error::io(&self.path))?;
+        file.write_all(contents.as_bytes())
+            .and_then(|_| file.flush())
+            .map_err(Error::io(path))?;
+        Ok(file)
+    }
+}
+
+impl Store for DirStore {
+    fn location(&self, key: &str) -> PathBuf {
+        self.entry_path(key)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let path = self.entry_path(key);
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(Error::Io { path, source }),
+        }
+    }
+
+    fn insert(&self, key: &str, contents: &str, replace: bool) -> Result<()> {
+        let path = self.entry_path(key);
+        if replace {
+            self.write_temp(&path, contents)?
+                .persist(&path)
+                .map_err(|e| Error::Io {
+                    path,
+                    source: e.error,
+                })?;
+            return Ok(());
+        }
+        if path.exists() {
+            return Ok(());
+        }
+        // Concurrent writers of the same key keep the first entry
+        match self.write_temp(&path, contents)?.persist_noclobber(&path) {
+            Err(e) if e.error.kind() != ErrorKind::AlreadyExists => Err(Error::Io {
+                path,
+                source: e.error,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn touch(&self, key: &str, used: SystemTime) -> Result<()> {
+        let path = self.entry_path(key);
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(Error::io(&path))?;
+        if used
+            .duration_since(modified)
+            .is_ok_and(|since| since < USE_PRECISION)
+        {
+            return Ok(());
+        }
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(used))
+            .map_err(Error::io(path))
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        let path = self.entry_path(key);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(Error::Io { path, source: e }),
+            _ => Ok(()),
+        }
+    }
+
+    fn entries(&self) -> Result<Vec<StoreEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.path).map_err(Error::io(&self.path))? {
+            let entry = entry.map_err(Error::io(&self.path))?;
+            let metadata = entry.metadata().map_err(Error::io(entry.path()))?;
+            // Skip temporary files of unfinished writes
+            let key = entry.file_name().to_string_lossy().to_string();
+            if metadata.is_file() && !key.starts_with(".tmp") {
+                entries.push(StoreEntry {
+                    key,
+                    bytes: metadata.len(),
+                    used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                });
+            }
+        }
+        Ok(entries)
+    }
+}
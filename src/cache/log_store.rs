@@ -0,0 +1,478 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+use super::{Store, StoreEntry, USE_PRECISION};
+use crate::{Error, Result};
+
+/// All entries in one append-only file, indexed in memory when opened.
+/// Each record is a JSON header line, followed for new entries by their
+/// contents and a newline. Replaced and removed entries take space until
+/// the cache is compacted, which rewrites the file. What other processes
+/// append meanwhile is lost, which only costs reading those photos again.
+///
+/// Processes sharing the file lock it to append, and pick up what others
+/// appended when a key is missing.
+pub struct LogStore {
+    path: PathBuf,
+    log: Mutex<Log>,
+}
+
+struct Log {
+    file: File,
+    /// End of the last complete record read or written
+    end: u64,
+    index: HashMap<String, Slot>,
+    /// Uses not written yet
+    used: HashMap<String, SystemTime>,
+}
+
+struct Slot {
+    offset: u64,
+    len: u64,
+    used: SystemTime,
+    /// Length of the whole record, header included
+    size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Record {
+    Put { key: String, len: u64, used: u64 },
+    Use { key: String, used: u64 },
+    Remove { key: String },
+}
+
+impl LogStore {
+    pub fn new(path: &Path) -> Result<LogStore> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(Error::io(dir))?;
+        }
+        let log = Log::open(path).map_err(Error::io(path))?;
+        let store = LogStore {
+            path: path.to_path_buf(),
+            log: Mutex::new(log),
+        };
+        store.with_log(|log| log.read_new(&store.path))?;
+        Ok(store)
+    }
+
+    fn with_log<T>(&self, f: impl FnOnce(&mut Log) -> Result<T>) -> Result<T> {
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut log)
+    }
+
+    /// Run `f` while holding the file lock, after reading the records other
+    /// processes appended
+    fn locked<T>(&self, log: &mut Log, f: impl FnOnce(&mut Log) -> Result<T>) -> Result<T> {
+        log.file.lock().map_err(Error::io(&self.path))?;
+        let result = log.read_new(&self.path).and_then(|_| f(log));
+        let _ = log.file.unlock();
+        result
+    }
+
+    /// Append records, which is only done while holding the file lock
+    fn append(&self, log: &mut Log, records: &[(Record, Option<&str>)]) -> Result<()> {
+        let mut bytes = Vec::new();
+        for (record, contents) in records {
+            serde_json::to_writer(&mut bytes, record).map_err(|source| Error::Json {
+                path: self.path.clone(),
+                source,
+            })?;
+            bytes.push(b'\n');
+            if let Some(contents) = contents {
+                bytes.extend_from_slice(contents.as_bytes());
+                bytes.push(b'\n');
+            }
+        }
+        // Drop what a crashed writer left half-written
+        log.file
+            .set_len(log.end)
+            .and_then(|_| log.file.write_all(&bytes))
+            .map_err(Error::io(&self.path))?;
+        log.index_records(&bytes, &self.path)
+    }
+
+    /// Write the live entries to a new file replacing this one
+    fn rewrite(&self, log: &mut Log) -> Result<()> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        let mut temp = NamedTempFile::new_in(dir).map_err(Error::io(dir))?;
+        let mut keys: Vec<_> = log.index.keys().cloned().collect();
+        keys.sort();
+        {
+            let mut writer = io::BufWriter::new(temp.as_file_mut());
+            for key in keys {
+                let contents = log.read_slot(&key, &self.path)?.unwrap_or_default();
+                let slot = &log.index[&key];
+                let record = Record::Put {
+                    key,
+                    len: contents.len() as u64,
+                    used: to_nanos(slot.used),
+                };
+                serde_json::to_writer(&mut writer, &record).map_err(|source| Error::Json {
+                    path: self.path.clone(),
+                    source,
+                })?;
+                writeln!(writer, "\n{}", contents).map_err(Error::io(&self.path))?;
+            }
+            writer.flush().map_err(Error::io(&self.path))?;
+        }
+        temp.persist(&self.path).map_err(|e| Error::Io {
+            path: self.path.clone(),
+            source: e.error,
+        })?;
+        *log = Log::open(&self.path).map_err(Error::io(&self.path))?;
+        log.read_new(&self.path)
+    }
+}
+
+impl Log {
+    fn open(path: &Path) -> io::Result<Log> {
+        let file = File::options()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        Ok(Log {
+            file,
+            end: 0,
+            index: HashMap::new(),
+            used: HashMap::new(),
+        })
+    }
+
+    /// Index the complete records after the last one read. A record cut
+    /// short is left for its writer to finish, and one that cannot be read
+    /// ends the log as if cut short, so that the next append replaces it.
+    fn read_new(&mut self, path: &Path) -> Result<()> {
+        let len = self.file.metadata().map_err(Error::io(path))?.len();
+        if len <= self.end {
+            return Ok(());
+        }
+        self.file
+            .seek(SeekFrom::Start(self.end))
+            .map_err(Error::io(path))?;
+        let mut new = Vec::new();
+        (&self.file)
+            .take(len - self.end)
+            .read_to_end(&mut new)
+            .map_err(Error::io(path))?;
+        self.index_records(&new, path)
+    }
+
+    /// Index records found at the end of the log
+    fn index_records(&mut self, mut bytes: &[u8], path: &Path) -> Result<()> {
+        let mut header = Vec::new();
+        loop {
+            header.clear();
+            let header_len = bytes
+                .read_until(b'\n', &mut header)
+                .map_err(Error::io(path))?;
+            if header_len == 0 || !header.ends_with(b"\n") {
+                return Ok(());
+            }
+            // Left by a writer that crashed or did not take the lock
+            let Ok(record) = serde_json::from_slice::<Record>(&header) else {
+                return Ok(());
+            };
+            let offset = self.end + header_len as u64;
+            let mut record_len = header_len as u64;
+            match record {
+                Record::Put { key, len, used } => {
+                    // Contents and their newline, past which a corrupt
+                    // length may point
+                    let Some(contents_len) = len
+                        .checked_add(1)
+                        .filter(|contents_len| *contents_len <= bytes.len() as u64)
+                    else {
+                        return Ok(());
+                    };
+                    let (contents, rest) = bytes.split_at(contents_len as usize);
+                    if !contents.ends_with(b"\n") {
+                        return Ok(());
+                    }
+                    bytes = rest;
+                    record_len += contents_len;
+                    let used = from_nanos(used);
+                    let slot = Slot {
+                        offset,
+                        len,
+                        used,
+                        size: record_len,
+                    };
+                    self.index.insert(key, slot);
+                }
+                Record::Use { key, used } => {
+                    if let Some(slot) = self.index.get_mut(&key) {
+                        slot.used = from_nanos(used);
+                    }
+                }
+                Record::Remove { key } => {
+                    self.index.remove(&key);
+                }
+            }
+            self.end += record_len;
+        }
+    }
+
+    fn read_slot(&mut self, key: &str, path: &Path) -> Result<Option<String>> {
+        let Some(slot) = self.index.get(key) else {
+            return Ok(None);
+        };
+        let mut contents = vec![0; slot.len as usize];
+        self.file
+            .seek(SeekFrom::Start(slot.offset))
+            .and_then(|_| self.file.read_exact(&mut contents))
+            .map_err(Error::io(path))?;
+        String::from_utf8(contents)
+            .map(Some)
+            .map_err(|e| Error::Io {
+                path: path.to_path_buf(),
+                source: io::Error::new(ErrorKind::InvalidData, e),
+            })
+    }
+}
+
+impl Store for LogStore {
+    fn location(&self, _key: &str) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.with_log(|log| {
+            if !log.index.contains_key(key) {
+                log.read_new(&self.path)?;
+            }
+            log.read_slot(key, &self.path)
+        })
+    }
+
+    fn insert(&self, key: &str, contents: &str, replace: bool) -> Result<()> {
+        self.with_log(|log| {
+            if !replace {
+                log.read_new(&self.path)?;
+                if log.index.contains_key(key) {
+                    return Ok(());
+                }
+            }
+            self.locked(log, |log| {
+                // Another process may have written the key since
+                if !replace && log.index.contains_key(key) {
+                    return Ok(());
+                }
+                let record = Record::Put {
+                    key: key.to_string(),
+                    len: contents.len() as u64,
+                    used: to_nanos(SystemTime::now()),
+                };
+                self.append(log, &[(record, Some(contents))])
+            })
+        })
+    }
+
+    fn touch(&self, key: &str, used: SystemTime) -> Result<()> {
+        self.with_log(|log| {
+            if let Some(slot) = log.index.get_mut(key) {
+                let since = used.duration_since(slot.used);
+                if since.map_or(true, |since| since >= USE_PRECISION) {
+                    slot.used = used;
+                    log.used.insert(key.to_string(), used);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.with_log(|log| {
+            log.read_new(&self.path)?;
+            if !log.index.contains_key(key) {
+                return Ok(());
+            }
+            log.used.remove(key);
+            let record = Record::Remove {
+                key: key.to_string(),
+            };
+            self.locked(log, |log| self.append(log, &[(record, None)]))
+        })
+    }
+
+    fn entries(&self) -> Result<Vec<StoreEntry>> {
+        self.with_log(|log| {
+            log.read_new(&self.path)?;
+            Ok(log
+                .index
+                .iter()
+                .map(|(key, slot)| StoreEntry {
+                    key: key.clone(),
+                    bytes: slot.len,
+                    used: slot.used,
+                })
+                .collect())
+        })
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.with_log(|log| {
+            let records: Vec<_> = log
+                .used
+                .drain()
+                .map(|(key, used)| {
+                    let used = to_nanos(used);
+                    (Record::Use { key, used }, None)
+                })
+                .collect();
+            if records.is_empty() {
+                return Ok(());
+            }
+            self.locked(log, |log| self.append(log, &records))
+        })
+    }
+
+    fn should_compact(&self) -> Result<bool> {
+        self.with_log(|log| {
+            let live: u64 = log.index.values().map(|slot| slot.size).sum();
+            Ok(log.end.saturating_sub(live) > live)
+        })
+    }
+
+    fn compact(&self) -> Result<()> {
+        self.flush()?;
+        self.with_log(|log| self.locked(log, |log| self.rewrite(log)))
+    }
+}
+
+// Errors are reported by an explicit flush before
+impl Drop for LogStore {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+fn to_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|since| u64::try_from(since.as_nanos()).ok())
+        .unwrap_or_default()
+}
+
+fn from_nanos(nanos: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_nanos(nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn append_and_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.log");
+        let store = LogStore::new(&path).unwrap();
+        store.insert("a", r#"{"n":1}"#, false).unwrap();
+        store.insert("b", "{\n}", false).unwrap();
+        store.insert("a", r#"{"n":2}"#, false).unwrap();
+        assert_eq!(store.get("a").unwrap().unwrap(), r#"{"n":1}"#);
+        assert_eq!(store.location("a"), path);
+        store.insert("a", r#"{"n":3}"#, true).unwrap();
+        store.remove("b").unwrap();
+        let long_ago = UNIX_EPOCH + Duration::from_secs(1000);
+        store.insert("c", "{}", false).unwrap();
+        store.touch("c", long_ago).unwrap();
+        drop(store);
+
+        // Another process appends a record, and crashes while writing one
+        let other = LogStore::new(&path).unwrap();
+        other.insert("d", "[]", false).unwrap();
+        let mut file = File::options().append(true).open(&path).unwrap();
+        file.write_all(br#"{"put":{"key":"e","len":100,"used":0}}"#)
+            .unwrap();
+        file.write_all(b"\n{").unwrap();
+
+        let store = LogStore::new(&path).unwrap();
+        assert_eq!(store.get("a").unwrap().unwrap(), r#"{"n":3}"#);
+        assert_eq!(store.get("b").unwrap(), None);
+        assert_eq!(store.get("d").unwrap().unwrap(), "[]");
+        assert_eq!(store.get("e").unwrap(), None);
+        let mut entries = store.entries().unwrap();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        let keys: Vec<_> = entries.iter().map(|entry| entry.key.as_str()).collect();
+        assert_eq!(keys, ["a", "c", "d"]);
+        assert_eq!(entries[1].used, long_ago);
+
+        // The half-written record is dropped before appending
+        store.insert("f", "{}", false).unwrap();
+        assert_eq!(
+            LogStore::new(&path).unwrap().get("f").unwrap().unwrap(),
+            "{}"
+        );
+
+        let before = fs::metadata(&path).unwrap().len();
+        store.compact().unwrap();
+        assert!(fs::metadata(&path).unwrap().len() < before);
+        assert_eq!(store.get("a").unwrap().unwrap(), r#"{"n":3}"#);
+        let store = LogStore::new(&path).unwrap();
+        assert_eq!(store.entries().unwrap().len(), 4);
+        assert_eq!(store.get("c").unwrap().unwrap(), "{}");
+    }
+
+    #[test]
+    fn skip_garbage_records() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.log");
+        LogStore::new(&path)
+            .unwrap()
+            .insert("a", "{}", false)
+            .unwrap();
+        let mut file = File::options().append(true).open(&path).unwrap();
+        file.write_all(b"not json\n\xff\n").unwrap();
+
+        let store = LogStore::new(&path).unwrap();
+        assert_eq!(store.get("a").unwrap().unwrap(), "{}");
+        store.insert("b", "[]", false).unwrap();
+
+        // The garbage was replaced by the next record
+        let store = LogStore::new(&path).unwrap();
+        assert_eq!(store.get("b").unwrap().unwrap(), "[]");
+        assert!(!fs::read_to_string(&path).unwrap().contains("not json"));
+    }
+
+    #[test]
+    fn stop_at_bad_lengths() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.log");
+        for tail in [
+            // Length past the end of any file
+            format!(r#"{{"put":{{"key":"b","len":{},"used":0}}}}"#, u64::MAX) + "\n{}\n",
+            // Length off by one, so the contents do not end in a newline
+            r#"{"put":{"key":"b","len":1,"used":0}}"#.to_string() + "\n{}\n",
+            // Header cut short
+            r#"{"put":{"key":"b","len":"#.to_string(),
+        ] {
+            fs::remove_file(&path).ok();
+            LogStore::new(&path)
+                .unwrap()
+                .insert("a", "{}", false)
+                .unwrap();
+            let mut file = File::options().append(true).open(&path).unwrap();
+            file.write_all(tail.as_bytes()).unwrap();
+            drop(file);
+
+            let store = LogStore::new(&path).unwrap();
+            assert_eq!(store.get("a").unwrap().unwrap(), "{}", "{}", tail);
+            assert_eq!(store.get("b").unwrap(), None, "{}", tail);
+            store.insert("c", "[]", false).unwrap();
+            let store = LogStore::new(&path).unwrap();
+            assert_eq!(store.get("c").unwrap().unwrap(), "[]", "{}", tail);
+        }
+    }
+}
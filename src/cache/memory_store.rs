@@ -0,0 +1,71 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex, time::SystemTime};
+
+use super::{Store, StoreEntry};
+use crate::Result;
+
+/// Entries kept only while the process runs, for when no cache can be
+/// opened
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, (String, SystemTime)>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    fn with_entries<T>(
+        &self,
+        f: impl FnOnce(&mut HashMap<String, (String, SystemTime)>) -> T,
+    ) -> T {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut entries)
+    }
+}
+
+impl Store for MemoryStore {
+    fn location(&self, _key: &str) -> PathBuf {
+        PathBuf::from("memory")
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.with_entries(|entries| entries.get(key).map(|(contents, _)| contents.clone())))
+    }
+
+    fn insert(&self, key: &str, contents: &str, replace: bool) -> Result<()> {
+        self.with_entries(|entries| {
+            if replace || !entries.contains_key(key) {
+                entries.insert(key.to_string(), (contents.to_string(), SystemTime::now()));
+            }
+        });
+        Ok(())
+    }
+
+    fn touch(&self, key: &str, used: SystemTime) -> Result<()> {
+        self.with_entries(|entries| {
+            if let Some((_, last_used)) = entries.get_mut(key) {
+                *last_used = used;
+            }
+        });
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.with_entries(|entries| entries.remove(key));
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<StoreEntry>> {
+        Ok(self.with_entries(|entries| {
+            entries
+                .iter()
+                .map(|(key, (contents, used))| StoreEntry {
+                    key: key.clone(),
+                    bytes: contents.len() as u64,
+                    used: *used,
+                })
+                .collect()
+        }))
+    }
+}
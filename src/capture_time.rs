@@ -0,0 +1,342 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+/// Time a photo was taken
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureTime {
+    /// Wall clock time where the photo was taken
+    pub local: NaiveDateTime,
+    /// Offset from UTC in seconds if known
+    pub offset: Option<i32>,
+}
+
+impl CaptureTime {
+    pub fn utc(&self) -> Option<NaiveDateTime> {
+        self.offset.and_then(|offset| {
+            self.local
+                .checked_sub_signed(Duration::try_seconds(offset as i64)?)
+        })
+    }
+
+    /// Parse Exif `2024:03:15 10:20:30` with optional `+01:00` offset
+    pub fn from_exif(datetime: &str, offset: Option<&str>) -> Option<CaptureTime> {
+        let local = NaiveDateTime::parse_from_str(datetime.trim(), "%Y:%m:%d %H:%M:%S").ok()?;
+        Some(CaptureTime {
+            local,
+            offset: offset.and_then(parse_offset),
+        })
+    }
+
+    /// Parse ISO 8601 such as `2024-03-15T10:20:30+0100` as written by
+    /// QuickTime and exiftool
+    pub fn from_iso8601(text: &str) -> Option<CaptureTime> {
+        let text = text.trim();
+        // Date and time take 19 characters; the rest is fraction and offset
+        let (datetime, rest) = text.split_at_checked(text.len().min(19))?;
+        let local = [
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y:%m:%d %H:%M:%S",
+            "%Y-%m-%d %H:%M:%S",
+        ]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(datetime, format).ok())?;
+        let offset = rest.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+        let offset = if offset.is_empty() {
+            None
+        } else {
+            Some(parse_offset(offset)?)
+        };
+        Some(CaptureTime { local, offset })
+    }
+
+    /// Merge local time with GPS time in UTC, deriving the offset if missing
+    pub fn merge(
+        local: Option<CaptureTime>,
+        gps_utc: Option<NaiveDateTime>,
+    ) -> Option<CaptureTime> {
+        match (local, gps_utc) {
+            (Some(local), Some(utc)) if local.offset.is_none() => {
+                // GPS fix and shutter differ by seconds, while time zones are
+                // in multiples of 15 minutes
+                let minutes = (local.local - utc).num_seconds() as f64 / 60.0;
+                let offset = (minutes / 15.0).round() as i32 * 15 * 60;
+                if offset.abs() <= 14 * 60 * 60 {
+                    Some(CaptureTime {
+                        offset: Some(offset),
+                        ..local
+                    })
+                } else {
+                    Some(local)
+                }
+            }
+            (Some(local), _) => Some(local),
+            (None, Some(utc)) => Some(CaptureTime {
+                local: utc,
+                offset: Some(0),
+            }),
+            (None, None) => None,
+        }
+    }
+
+    // Instants are compared if both offsets are known, otherwise wall clock
+    fn compare(&self, other: &CaptureTime) -> Ordering {
+        match (self.utc(), other.utc()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => self.local.cmp(&other.local),
+        }
+    }
+}
+
+/// Accept `2024-03-01`, `2024-03-01T10:00`, `2024-03-01 10:00:00` and the
+/// same with `Z` or `+01:00` offset
+impl FromStr for CaptureTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(CaptureTime {
+                local: date.and_time(NaiveTime::MIN),
+                offset: None,
+            });
+        }
+        if let Some(time) = CaptureTime::from_iso8601(s) {
+            return Ok(time);
+        }
+        // Without seconds
+        let (datetime, offset) = s
+            .split_at_checked(s.len().min(16))
+            .ok_or_else(|| format!("Invalid time: {}", s))?;
+        let local = ["%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(datetime, format).ok())
+            .ok_or_else(|| format!("Invalid time: {}", s))?;
+        let offset = match offset {
+            "" => None,
+            _ => Some(parse_offset(offset).ok_or_else(|| format!("Invalid time: {}", s))?),
+        };
+        Ok(CaptureTime { local, offset })
+    }
+}
+
+/// Parse `Z`, `+01:00`, `+0100` or `-05` into seconds east of UTC
+pub fn parse_offset(text: &str) -> Option<i32> {
+    let text = text.trim();
+    if text == "Z" {
+        return Some(0);
+    }
+    let sign = match text.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = text[1..].chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    Some(sign * (hours * 60 * 60 + minutes * 60))
+}
+
+/// Parse durations such as `90s`, `30min`, `2h` or `3d`
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (value, unit) = text.split_at(split);
+    let value: i64 = value
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", text))?;
+    let duration = match unit.trim() {
+        "s" => Duration::try_seconds(value),
+        "m" | "min" => Duration::try_minutes(value),
+        "h" => Duration::try_hours(value),
+        "d" => Duration::try_days(value),
+        _ => {
+            return Err(format!(
+                "Invalid duration {}, expected s, min, h or d",
+                text
+            ))
+        }
+    };
+    duration.ok_or_else(|| format!("Duration too long: {}", text))
+}
+
+/// Photos taken at or after `after` and before `before`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeWindow {
+    pub after: Option<CaptureTime>,
+    pub before: Option<CaptureTime>,
+}
+
+impl TimeWindow {
+    /// Photos taken within `margin` before or after `time`, up to the
+    /// earliest and latest representable times
+    pub fn around(time: CaptureTime, margin: Duration) -> TimeWindow {
+        let after = time
+            .local
+            .checked_sub_signed(margin)
+            .unwrap_or(NaiveDateTime::MIN);
+        // Before is exclusive
+        let before = time
+            .local
+            .checked_add_signed(margin)
+            .and_then(|before| before.checked_add_signed(Duration::nanoseconds(1)))
+            .unwrap_or(NaiveDateTime::MAX);
+        TimeWindow {
+            after: Some(CaptureTime {
+                local: after,
+                ..time
+            }),
+            before: Some(CaptureTime {
+                local: before,
+                ..time
+            }),
+        }
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.after.is_none() && self.before.is_none()
+    }
+
+    /// Photos without time are excluded unless the window is unbounded
+    pub fn contains(&self, time: Option<&CaptureTime>) -> bool {
+        if self.is_unbounded() {
+            return true;
+        }
+        let time = match time {
+            Some(time) => time,
+            None => return false,
+        };
+        let after = self
+            .after
+            .is_none_or(|after| time.compare(&after) != Ordering::Less);
+        let before = self
+            .before
+            .is_none_or(|before| time.compare(&before) == Ordering::Less);
+        after && before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn parse_exif() {
+        let time = CaptureTime::from_exif("2024:03:15 10:20:30", Some("+01:00")).unwrap();
+
+        assert_eq!(time.local, datetime("2024-03-15 10:20:30"));
+        assert_eq!(time.offset, Some(3600));
+        assert_eq!(time.utc(), Some(datetime("2024-03-15 09:20:30")));
+        assert!(CaptureTime::from_exif("0000:00:00 00:00:00", None).is_none());
+    }
+
+    #[test]
+    fn parse_iso8601() {
+        let time = CaptureTime::from_iso8601("2024-03-15T10:20:30+0100").unwrap();
+        assert_eq!(time.local, datetime("2024-03-15 10:20:30"));
+        assert_eq!(time.offset, Some(3600));
+
+        let time = CaptureTime::from_iso8601("2024:03:15 10:20:30.123-05:00").unwrap();
+        assert_eq!(time.offset, Some(-5 * 3600));
+
+        let time = CaptureTime::from_iso8601("2024-03-15T10:20:30Z").unwrap();
+        assert_eq!(time.offset, Some(0));
+
+        assert!(CaptureTime::from_iso8601("2024-03-15T10:20:30 CET").is_none());
+    }
+
+    #[test]
+    fn parse_bounds() {
+        let time: CaptureTime = "2024-03-01".parse().unwrap();
+        assert_eq!(time.local, datetime("2024-03-01 00:00:00"));
+        assert_eq!(time.offset, None);
+
+        let time: CaptureTime = "2024-03-01T08:30+09:00".parse().unwrap();
+        assert_eq!(time.local, datetime("2024-03-01 08:30:00"));
+        assert_eq!(time.offset, Some(9 * 3600));
+
+        let time: CaptureTime = "2024-03-01 08:30:15".parse().unwrap();
+        assert_eq!(time.local, datetime("2024-03-01 08:30:15"));
+
+        assert!("March 2024".parse::<CaptureTime>().is_err());
+        assert!("2024-03-01T08:30+9".parse::<CaptureTime>().is_err());
+    }
+
+    #[test]
+    fn merge_gps_offset() {
+        let local = CaptureTime::from_exif("2024:03:15 10:20:30", None);
+        let utc = datetime("2024-03-15 01:20:28");
+
+        let merged = CaptureTime::merge(local, Some(utc)).unwrap();
+        assert_eq!(merged.offset, Some(9 * 3600));
+
+        let merged = CaptureTime::merge(None, Some(utc)).unwrap();
+        assert_eq!(merged.local, utc);
+        assert_eq!(merged.offset, Some(0));
+
+        // Keep recorded offset
+        let local = CaptureTime::from_exif("2024:03:15 10:20:30", Some("+08:00"));
+        let merged = CaptureTime::merge(local, Some(utc)).unwrap();
+        assert_eq!(merged.offset, Some(8 * 3600));
+    }
+
+    #[test]
+    fn window_around() {
+        let time = CaptureTime::from_exif("2024:03:15 10:20:30", Some("+01:00")).unwrap();
+        let window = TimeWindow::around(time, parse_duration("1h").unwrap());
+
+        let at = |text: &str| CaptureTime::from_exif(text, Some("+01:00")).unwrap();
+        assert!(window.contains(Some(&at("2024:03:15 09:20:30"))));
+        assert!(window.contains(Some(&at("2024:03:15 11:20:30"))));
+        assert!(!window.contains(Some(&at("2024:03:15 11:20:31"))));
+
+        let window = TimeWindow::around(time, parse_duration("1000000000d").unwrap());
+        assert!(window.contains(Some(&at("0001:01:01 00:00:00"))));
+        assert!(window.contains(Some(&at("9999:12:31 23:59:59"))));
+
+        assert_eq!(parse_duration("30min"), Ok(Duration::minutes(30)));
+        assert_eq!(parse_duration("3d"), Ok(Duration::days(3)));
+        assert!(parse_duration("3 weeks").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("999999999999999d").is_err());
+    }
+
+    #[test]
+    fn window_contains() {
+        let window = TimeWindow {
+            after: Some("2024-03-01".parse().unwrap()),
+            before: Some("2024-04-01".parse().unwrap()),
+        };
+
+        let march = CaptureTime::from_exif("2024:03:31 23:59:59", None).unwrap();
+        let april = CaptureTime::from_exif("2024:04:01 00:00:00", Some("+02:00")).unwrap();
+        assert!(window.contains(Some(&march)));
+        assert!(!window.contains(Some(&april)));
+        assert!(!window.contains(None));
+        assert!(TimeWindow::default().contains(None));
+
+        // Both offsets known: compare instants
+        let window = TimeWindow {
+            after: Some("2024-03-01T00:00:00Z".parse().unwrap()),
+            before: None,
+        };
+        let tokyo = CaptureTime::from_exif("2024:03:01 08:00:00", Some("+09:00")).unwrap();
+        assert!(!window.contains(Some(&tokyo)));
+    }
+}
@@ -0,0 +1,78 @@
+use std::{fmt, io, path::PathBuf};
+
+/// Failure to read a photo, the cache or a geocoding response. Searches
+/// report these per file and go on with the next one.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed
+    Io { path: PathBuf, source: io::Error },
+    /// A cache entry is not the expected JSON
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// exiftool could not be started or its answer not be read
+    Exiftool { path: PathBuf, source: io::Error },
+    /// A file of areas, routes or targets has none or is malformed
+    Invalid { path: PathBuf, message: String },
+    /// Photos to search could not be listed
+    Walk(String),
+    /// Threads for scanning in parallel could not be started
+    Threads {
+        jobs: usize,
+        source: rayon::ThreadPoolBuildError,
+    },
+    /// Nominatim could not be reached or answered with an error
+    Geocode {
+        address: String,
+        source: reqwest::Error,
+    },
+    /// Nominatim answered with a place that cannot be searched
+    GeocodeAnswer { address: String, message: String },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Error {
+        let path = path.into();
+        move |source| Error::Io { path, source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Json { path, source } => {
+                write!(f, "Invalid JSON in {}: {}", path.display(), source)
+            }
+            Error::Exiftool { path, source } => {
+                write!(f, "exiftool failed on {}: {}", path.display(), source)
+            }
+            Error::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+            Error::Walk(message) => write!(f, "{}", message),
+            Error::Threads { jobs, source } => {
+                write!(f, "Cannot start {} threads: {}", jobs, source)
+            }
+            Error::Geocode { address, source } => {
+                write!(f, "Cannot geocode {}: {}", address, source)
+            }
+            Error::GeocodeAnswer { address, message } => {
+                write!(f, "Cannot geocode {}: {}", address, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::Exiftool { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            Error::Geocode { source, .. } => Some(source),
+            Error::Threads { source, .. } => Some(source),
+            Error::Invalid { .. } | Error::Walk(_) | Error::GeocodeAnswer { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    ffi::OsStr,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+const READY: &str = "{ready}";
+
+/// Long-running `exiftool -stay_open` process, so that the Perl interpreter
+/// starts once instead of once per file
+pub struct ExifTool {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExifTool {
+    pub fn spawn(program: &OsStr) -> io::Result<ExifTool> {
+        let mut child = Command::new(program)
+            .args(["-stay_open", "True", "-@", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        Ok(ExifTool {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Tags of a file as exiftool JSON, `None` if exiftool found nothing
+    pub fn read_json(&mut self, path: &Path) -> io::Result<Option<serde_json::Value>> {
+        // Arguments are read one per line, and a relative path starting with
+        // `-` would be taken for an option
+        let path = if path.is_relative() {
+            Path::new(".").join(path)
+        } else {
+            path.to_path_buf()
+        };
+        let path = path.to_string_lossy();
+        if path.contains('\n') {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "Path contains a newline",
+            ));
+        }
+        write!(self.stdin, "-json\n{}\n-execute\n", path)?;
+        self.stdin.flush()?;
+
+        let mut json = String::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "exiftool exited unexpectedly",
+                ));
+            }
+            if line.trim_end() == READY {
+                break;
+            }
+            json.push_str(&line);
+        }
+        if json.trim().is_empty() {
+            return Ok(None);
+        }
+        let mut value: serde_json::Value = serde_json::from_str(&json)?;
+        Ok(Some(value[0].take()))
+    }
+}
+
+impl Drop for ExifTool {
+    fn drop(&mut self) {
+        if self
+            .stdin
+            .write_all(b"-stay_open\nFalse\n")
+            .and_then(|_| self.stdin.flush())
+            .is_err()
+        {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::ffi::OsString;
+    #[cfg(unix)]
+    use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
+
+    use super::*;
+    #[cfg(unix)]
+    use tempfile::tempdir;
+
+    /// Script answering like `exiftool -stay_open` with the same coordinates
+    /// for every file except `missing.jpg`, and appending to `starts.log` in
+    /// its directory each time it is launched
+    #[cfg(unix)]
+    pub(crate) fn fake_exiftool(dir: &Path) -> PathBuf {
+        let path = dir.join("exiftool");
+        let script = r#"#!/bin/sh
+echo started >> "$(dirname "$0")/starts.log"
+while read -r arg; do
+    case "$arg" in
+        -stay_open|-json) ;;
+        False) exit 0 ;;
+        -execute)
+            case "$file" in
+                *missing.jpg) ;;
+                *) printf '[{"SourceFile": "%s", "GPSLatitude": "40 deg 26%s 46.00\\" N", "GPSLongitude": "79 deg 58%s 56.00\\" W", "DateTimeOriginal": "2024:05:01 12:00:00"}]\n' "$file" "'" "'" ;;
+            esac
+            echo "{ready}"
+            ;;
+        *) file="$arg" ;;
+    esac
+done
+"#;
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_from_session() {
+        let temp_dir = tempdir().unwrap();
+        let program = fake_exiftool(temp_dir.path());
+
+        let mut exiftool = ExifTool::spawn(program.as_os_str()).unwrap();
+        let value = exiftool.read_json(Path::new("a.mp4")).unwrap().unwrap();
+        assert_eq!(value["SourceFile"], "./a.mp4");
+        assert_eq!(value["GPSLatitude"], "40 deg 26' 46.00\" N");
+        assert!(exiftool
+            .read_json(Path::new("missing.jpg"))
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            exiftool.read_json(Path::new("b.mov")).unwrap().unwrap()["SourceFile"],
+            "./b.mov"
+        );
+        assert_eq!(
+            exiftool.read_json(Path::new("-json")).unwrap().unwrap()["SourceFile"],
+            "./-json"
+        );
+        assert_eq!(
+            exiftool
+                .read_json(Path::new("/tmp/c.mov"))
+                .unwrap()
+                .unwrap()["SourceFile"],
+            "/tmp/c.mov"
+        );
+        assert!(exiftool.read_json(Path::new("new\nline.jpg")).is_err());
+        drop(exiftool);
+
+        let starts = fs::read_to_string(temp_dir.path().join("starts.log")).unwrap();
+        assert_eq!(starts.lines().count(), 1);
+    }
+
+    #[test]
+    fn spawn_missing_program() {
+        let program = OsString::from("/nonexistent/exiftool");
+        assert!(ExifTool::spawn(&program).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+// Compatible brands marking an ISO-BMFF file as HEIF image rather than video
+const HEIF_BRANDS: [&[u8; 4]; 2] = [b"mif1", b"msf1"];
+
+/// Container format detected from the leading bytes of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Tiff,
+    Png,
+    WebP,
+    Heif,
+    QuickTime,
+    /// Olympus RAW, TIFF with its own magic number
+    Orf,
+    /// Panasonic RAW, TIFF with its own magic number
+    Rw2,
+    /// Canon RAW version 3, ISO-BMFF with TIFF blobs
+    Cr3,
+    /// Fujifilm RAW, embedding a JPEG preview with Exif
+    Raf,
+}
+
+pub fn sniff_file(path: &Path) -> io::Result<Option<Format>> {
+    let mut header = Vec::new();
+    File::open(path)?.take(64).read_to_end(&mut header)?;
+    Ok(sniff(&header))
+}
+
+pub fn sniff(header: &[u8]) -> Option<Format> {
+    if header.starts_with(&[0xff, 0xd8]) {
+        Some(Format::Jpeg)
+    } else if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        // Also DNG, CR2, NEF and ARW
+        Some(Format::Tiff)
+    } else if [b"IIRO", b"IIRS", b"MMOR"]
+        .iter()
+        .any(|magic| header.starts_with(*magic))
+    {
+        Some(Format::Orf)
+    } else if header.starts_with(b"IIU\0") {
+        Some(Format::Rw2)
+    } else if header.starts_with(b"FUJIFILMCCD-RAW ") {
+        Some(Format::Raf)
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(Format::Png)
+    } else if header.len() >= 12 && &header[..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+        Some(Format::WebP)
+    } else if header.len() >= 8 && &header[4..8] == b"ftyp" {
+        if header.get(8..12) == Some(b"crx ") {
+            Some(Format::Cr3)
+        } else if is_heif_ftyp(header) {
+            Some(Format::Heif)
+        } else {
+            Some(Format::QuickTime)
+        }
+    } else if header.len() >= 8
+        && matches!(
+            &header[4..8],
+            b"moov" | b"mdat" | b"wide" | b"free" | b"skip"
+        )
+    {
+        // Older QuickTime files may start without ftyp
+        Some(Format::QuickTime)
+    } else {
+        None
+    }
+}
+
+fn is_heif_ftyp(header: &[u8]) -> bool {
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let end = size.min(header.len());
+    // Skip size, type, major brand and minor version
+    header
+        .get(16..end)
+        .unwrap_or_default()
+        .chunks_exact(4)
+        .any(|brand| HEIF_BRANDS.iter().any(|heif| brand == *heif))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_images() {
+        assert_eq!(sniff(b"\xff\xd8\xff\xe1\0\0Exif"), Some(Format::Jpeg));
+        assert_eq!(sniff(b"II*\0\x08\0\0\0"), Some(Format::Tiff));
+        assert_eq!(sniff(b"MM\0*\0\0\0\x08"), Some(Format::Tiff));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"), Some(Format::Png));
+        assert_eq!(sniff(b"RIFF\x10\0\0\0WEBPVP8 "), Some(Format::WebP));
+        assert_eq!(
+            sniff(b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"),
+            Some(Format::Heif)
+        );
+    }
+
+    #[test]
+    fn sniff_raw() {
+        assert_eq!(sniff(b"II*\0\x10\0\0\0CR\x02\0"), Some(Format::Tiff));
+        assert_eq!(sniff(b"IIRO\x08\0\0\0"), Some(Format::Orf));
+        assert_eq!(sniff(b"MMOR\0\0\0\x08"), Some(Format::Orf));
+        assert_eq!(sniff(b"IIU\0\x08\0\0\0"), Some(Format::Rw2));
+        assert_eq!(
+            sniff(b"\0\0\0\x18ftypcrx \0\0\0\x01crx isom"),
+            Some(Format::Cr3)
+        );
+        assert_eq!(sniff(b"FUJIFILMCCD-RAW 0201FF383501"), Some(Format::Raf));
+    }
+
+    #[test]
+    fn sniff_videos() {
+        assert_eq!(
+            sniff(b"\0\0\0\x18ftypisom\0\0\x02\0isommp41"),
+            Some(Format::QuickTime)
+        );
+        assert_eq!(
+            sniff(b"\0\0\0\x14ftypqt  \0\0\0\0qt  "),
+            Some(Format::QuickTime)
+        );
+        assert_eq!(
+            sniff(b"\0\0\0\x08wide\0\0\0\0mdat"),
+            Some(Format::QuickTime)
+        );
+    }
+
+    #[test]
+    fn sniff_unknown() {
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"GIF89a"), None);
+        assert_eq!(sniff(b"plain text file"), None);
+    }
+}
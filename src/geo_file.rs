@@ -0,0 +1,98 @@
+use std::{fs, path::Path};
+
+use crate::{Error, Result};
+
+/// Syntax of a file of shapes or points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Syntax {
+    /// GeoJSON
+    Json,
+    /// KML or GPX
+    Xml,
+    /// WKT or CSV
+    Text,
+}
+
+/// Read a file with `parse`, given its syntax from the extension or else
+/// from the first character. `what` names the content in errors, such as
+/// "polygon", which is missing when `is_empty`.
+pub(crate) fn read<T>(
+    path: &Path,
+    what: &str,
+    parse: impl FnOnce(Syntax, &str) -> Result<T, String>,
+    is_empty: impl FnOnce(&T) -> bool,
+) -> Result<T> {
+    let contents = fs::read_to_string(path).map_err(Error::io(path))?;
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let syntax = match ext.as_str() {
+        "geojson" | "json" => Syntax::Json,
+        "kml" | "gpx" => Syntax::Xml,
+        "wkt" | "csv" => Syntax::Text,
+        _ => match contents.trim_start().chars().next() {
+            Some('{') => Syntax::Json,
+            Some('<') => Syntax::Xml,
+            _ => Syntax::Text,
+        },
+    };
+    let invalid = |message| Error::Invalid {
+        path: path.to_path_buf(),
+        message,
+    };
+    let found =
+        parse(syntax, &contents).map_err(|e| invalid(format!("Invalid {}: {}", what, e)))?;
+    if is_empty(&found) {
+        return Err(invalid(format!("Found no {}", what)));
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Read contents written to a temporary file with this name
+    pub(crate) fn read_temp<T>(
+        name: &str,
+        contents: &str,
+        read: impl FnOnce(&Path) -> Result<T>,
+    ) -> Result<T> {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        read(&path)
+    }
+
+    #[test]
+    fn read_by_syntax() {
+        let syntax = |name, contents| {
+            read_temp(name, contents, |path| {
+                read(path, "thing", |syntax, _| Ok(syntax), |_| false)
+            })
+            .unwrap()
+        };
+        assert_eq!(syntax("a.GeoJSON", "<"), Syntax::Json);
+        assert_eq!(syntax("a.gpx", "{"), Syntax::Xml);
+        assert_eq!(syntax("a.txt", " {}"), Syntax::Json);
+        assert_eq!(syntax("a", "<kml/>"), Syntax::Xml);
+        assert_eq!(syntax("a", "POINT (1 2)"), Syntax::Text);
+
+        let error = read_temp("a.csv", "", |path| {
+            read(path, "thing", |_, _| Ok(()), |_| true)
+        });
+        assert!(error.unwrap_err().to_string().ends_with("Found no thing"));
+        assert!(matches!(
+            read(
+                Path::new("/nonexistent/a.csv"),
+                "thing",
+                |_, _| Ok(()),
+                |_| false
+            ),
+            Err(Error::Io { .. })
+        ));
+    }
+}
@@ -1,25 +1,50 @@
+use geojson::GeoJson;
 use reqwest::{
     blocking::Client,
     header::{HeaderMap, HeaderValue, USER_AGENT},
 };
 use serde_json::Value;
 
-use imnear::Cache;
+use imnear::{Area, BoundingBox, Cache, Error, Result};
 
-pub fn locate(address: &str, cache: Cache) -> Option<(f64, f64)> {
-    let res_json = match cache.read(address) {
-        Some(res_json) => res_json,
+pub fn locate(address: &str, cache: &Cache) -> Result<Option<(f64, f64)>> {
+    let res_json = cached_request(address, address, &[], cache)?;
+    Ok(json_to_coords(res_json))
+}
+
+/// Find the boundary of a place, falling back to its bounding box when
+/// Nominatim has no polygon for it, e.g. for nodes
+pub fn locate_area(address: &str, cache: &Cache) -> Result<Option<Area>> {
+    // Responses with polygons are cached apart from plain ones
+    let key = format!("{} polygon", address);
+    let res_json = cached_request(&key, address, &[("polygon_geojson", "1")], cache)?;
+    json_to_area(res_json).map_err(|message| Error::GeocodeAnswer {
+        address: address.to_string(),
+        message,
+    })
+}
+
+fn cached_request(
+    key: &str,
+    address: &str,
+    extra_params: &[(&str, &str)],
+    cache: &Cache,
+) -> Result<Value> {
+    match cache.read(key)? {
+        Some(res_json) => Ok(res_json),
         None => {
             eprintln!("Address cache miss");
-            let res_json = request_api(address);
-            cache.write(address, res_json.clone());
-            res_json
+            let res_json = request_api(address, extra_params)?;
+            // The response is fine even if it cannot be cached
+            if let Err(e) = cache.write(key, res_json.clone()) {
+                eprintln!("Warning: {}", e);
+            }
+            Ok(res_json)
         }
-    };
-    json_to_coords(res_json)
+    }
 }
 
-fn request_api(address: &str) -> Value {
+fn request_api(address: &str, extra_params: &[(&str, &str)]) -> Result<Value> {
     let client = Client::new();
     let url = "https://nominatim.openstreetmap.org/search";
     let mut headers = HeaderMap::new();
@@ -28,15 +53,21 @@ fn request_api(address: &str) -> Value {
         USER_AGENT,
         HeaderValue::from_static("indie image searcher v0.1.0"),
     );
-    let params = vec![("q", address), ("format", "geojson")];
-    let url_with_params = reqwest::Url::parse_with_params(url, &params).unwrap();
+    let mut params = vec![("q", address), ("format", "geojson")];
+    params.extend_from_slice(extra_params);
+    let url_with_params =
+        reqwest::Url::parse_with_params(url, &params).expect("Nominatim URL is valid");
 
-    let res = client.get(url_with_params).headers(headers).send();
-    let res_json = match res {
-        Ok(res) => res.json::<serde_json::Value>().unwrap(),
-        Err(e) => panic!("err: {:?}", e),
-    };
-    res_json
+    client
+        .get(url_with_params)
+        .headers(headers)
+        .send()
+        .and_then(|res| res.error_for_status())
+        .and_then(|res| res.json::<serde_json::Value>())
+        .map_err(|source| Error::Geocode {
+            address: address.to_string(),
+            source,
+        })
 }
 
 fn json_to_coords(json_response: serde_json::Value) -> Option<(f64, f64)> {
@@ -52,6 +83,33 @@ fn json_to_coords(json_response: serde_json::Value) -> Option<(f64, f64)> {
     Some((lat, lon))
 }
 
+// `None` if no place was found, and an error if its bounding box is invalid
+fn json_to_area(json_response: serde_json::Value) -> Result<Option<Area>, String> {
+    let feature = &json_response["features"][0];
+    if let Ok(geojson) = GeoJson::from_json_value(feature.clone()) {
+        if let Ok(polygons) = imnear::polygon::from_geojson(&geojson) {
+            if !polygons.0.is_empty() {
+                return Ok(Some(Area::Polygon(polygons)));
+            }
+        }
+    }
+
+    let Some(bbox) = feature["bbox"].as_array().and_then(|bbox| {
+        bbox.iter()
+            .map(|value| value.as_f64())
+            .collect::<Option<Vec<_>>>()
+    }) else {
+        return Ok(None);
+    };
+    match bbox[..] {
+        [min_lon, min_lat, max_lon, max_lat] => {
+            let bbox = BoundingBox::new(min_lon, min_lat, max_lon, max_lat)?;
+            Ok(Some(Area::BoundingBox(bbox)))
+        }
+        _ => Ok(None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -146,7 +204,7 @@ mod tests {
   ]
 }
 "#;
-        let des = serde_json::from_str(&json_response).unwrap();
+        let des = serde_json::from_str(json_response).unwrap();
 
         let coords = json_to_coords(des).unwrap();
 
@@ -156,10 +214,75 @@ mod tests {
     #[test]
     fn json_to_coords_missing_key() {
         let json_response = r#"{"features": []}"#;
-        let des = serde_json::from_str(&json_response).unwrap();
+        let des = serde_json::from_str(json_response).unwrap();
 
         let coords = json_to_coords(des);
 
         assert!(coords.is_none());
     }
+
+    #[test]
+    fn json_to_area_polygon() {
+        let json_response = r#"
+{
+  "type": "FeatureCollection",
+  "features": [
+    {
+      "type": "Feature",
+      "properties": {"name": "Square"},
+      "bbox": [0.0, 0.0, 10.0, 10.0],
+      "geometry": {
+        "type": "Polygon",
+        "coordinates": [
+          [[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+          [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]
+        ]
+      }
+    }
+  ]
+}
+"#;
+        let des = serde_json::from_str(json_response).unwrap();
+
+        let area = json_to_area(des).unwrap().unwrap();
+
+        assert!(matches!(area, Area::Polygon(_)));
+        assert!(area.contains((2.0, 2.0), 0.0));
+        assert!(!area.contains((5.0, 5.0), 0.0));
+    }
+
+    #[test]
+    fn json_to_area_bbox_fallback() {
+        let json_response = r#"
+{
+  "type": "FeatureCollection",
+  "features": [
+    {
+      "type": "Feature",
+      "properties": {"name": "Yellowstone"},
+      "bbox": [-89.9904023, 42.7783343, -89.9504023, 42.8183343],
+      "geometry": {
+        "type": "Point",
+        "coordinates": [-89.9704023, 42.7983343]
+      }
+    }
+  ]
+}
+"#;
+        let des = serde_json::from_str(json_response).unwrap();
+
+        let area = json_to_area(des).unwrap().unwrap();
+
+        assert!(matches!(area, Area::BoundingBox(_)));
+        assert!(area.contains((42.79, -89.97), 0.0));
+
+        let des = serde_json::from_str(r#"{"features": []}"#).unwrap();
+        assert!(json_to_area(des).unwrap().is_none());
+
+        // Latitudes swapped
+        let des =
+            serde_json::from_str(r#"{"features": [{"bbox": [-89.99, 42.81, -89.95, 42.77]}]}"#)
+                .unwrap();
+        assert!(json_to_area(des).is_err());
+    }
 }
@@ -0,0 +1,92 @@
+use std::{fmt, str::FromStr};
+
+const UNITS: [(&str, f64); 5] = [
+    ("m", 1.0),
+    ("km", 1000.0),
+    ("mi", 1609.344),
+    ("nmi", 1852.0),
+    ("ft", 0.3048),
+];
+
+/// Distance such as a search radius. Parses `500m`, `5km`, `3mi`, `2nmi`
+/// and `1000ft`, with bare numbers in meters.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct Length(f64);
+
+impl Length {
+    pub fn from_meters(meters: f64) -> Length {
+        Length(meters)
+    }
+
+    pub fn meters(&self) -> f64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}m", self.0)
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        // The unit is taken from the end, as numbers like 1e3 have letters
+        let split = s.trim_end_matches(|c: char| c.is_ascii_alphabetic()).len();
+        let (value, unit) = s.split_at(split);
+        let value: f64 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid distance: {}", s))?;
+        let unit = unit.to_ascii_lowercase();
+        let factor = match unit.as_str() {
+            "" => 1.0,
+            unit => UNITS
+                .iter()
+                .find(|(name, _)| *name == unit)
+                .map(|(_, factor)| *factor)
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown unit {} in {}, expected m, km, mi, nmi or ft",
+                        unit, s
+                    )
+                })?,
+        };
+        if !value.is_finite() || value < 0.0 {
+            return Err(format!("Invalid distance: {}", s));
+        }
+        Ok(Length(value * factor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meters(text: &str) -> f64 {
+        text.parse::<Length>().unwrap().meters()
+    }
+
+    #[test]
+    fn parse_units() {
+        assert_eq!(meters("500"), 500.0);
+        assert_eq!(meters("500m"), 500.0);
+        assert_eq!(meters("5km"), 5000.0);
+        assert_eq!(meters("5 KM"), 5000.0);
+        assert_eq!(meters("0.5km"), 500.0);
+        assert_eq!(meters("3mi"), 3.0 * 1609.344);
+        assert_eq!(meters("2nmi"), 3704.0);
+        assert!((meters("1000ft") - 304.8).abs() < 1e-9);
+        assert_eq!(meters("1e3"), 1000.0);
+        assert_eq!(meters("1e3m"), 1000.0);
+        assert_eq!(meters("2.5E1 km"), 25000.0);
+
+        assert!("5 parsecs".parse::<Length>().is_err());
+        assert!("-5km".parse::<Length>().is_err());
+        assert!("km".parse::<Length>().is_err());
+        assert!("inf".parse::<Length>().is_err());
+    }
+}
@@ -1,23 +1,71 @@
 use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    ffi::OsString,
+    fmt, io, iter,
     path::{Path, PathBuf},
-    process::Command,
+    sync::{Mutex, OnceLock},
 };
 
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
 use geo::{Distance, Haversine, Point};
+use rayon::prelude::*;
 
-pub use cache::Cache;
-pub use photo_metadata::PhotoMetadata;
+pub use area::{Area, BoundingBox};
+pub use cache::{Cache, CacheLimits, DirStore, LogStore, Store};
+use capture_time::parse_offset;
+pub use capture_time::{CaptureTime, TimeWindow};
+pub use error::{Error, Result};
+use exiftool::ExifTool;
+pub use format::Format;
+pub use length::Length;
+pub use location::Location;
+pub use photo_metadata::{CacheCheck, FileStamp, MetadataSource, PhotoMetadata, Precedence};
+pub use route::Route;
+pub use target::{Target, TargetDistance};
+pub use walk::Walker;
 
+pub mod area;
 pub mod cache;
+pub mod capture_time;
+mod error;
+mod exiftool;
+pub mod format;
+mod geo_file;
+pub mod length;
+pub mod location;
 pub mod photo_metadata;
+pub mod polygon;
+mod quicktime;
+mod raw;
+pub mod route;
+mod takeout;
+pub mod target;
+pub mod walk;
+mod xmp;
+
+// Files per thread handed out at a time when scanning in parallel
+const BATCH_PER_JOB: usize = 16;
 
 pub struct Searcher {
-    radius: f64,
-    target_loc: (f64, f64),
+    area: Area,
     early_stop_count: isize,
     sort_by_distance: bool,
+    sort_by_route: bool,
+    nearest: Option<usize>,
+    min_distance: f64,
+    excluded: Vec<PathBuf>,
+    jobs: usize,
     verbose: bool,
     cache: Cache,
+    cache_check: CacheCheck,
+    sidecar_precedence: Precedence,
+    time_window: TimeWindow,
+    exiftool_program: OsString,
+    // Idle sessions, started on first use and taken by one thread at a time
+    exiftool: Mutex<Vec<ExifTool>>,
+    // Why exiftool could not be started, so that it is only tried once
+    exiftool_failure: OnceLock<(io::ErrorKind, String)>,
 }
 
 impl Searcher {
@@ -28,30 +76,231 @@ impl Searcher {
         sort_by_distance: bool,
         verbose: bool,
         cache: Cache,
+    ) -> Searcher {
+        Searcher::with_area(
+            Area::Circle {
+                center: target_loc,
+                radius,
+            },
+            early_stop_count,
+            sort_by_distance,
+            verbose,
+            cache,
+        )
+    }
+
+    /// Search in an area other than a circle around a target location
+    pub fn with_area(
+        area: Area,
+        early_stop_count: isize,
+        sort_by_distance: bool,
+        verbose: bool,
+        cache: Cache,
     ) -> Searcher {
         Searcher {
-            radius,
-            target_loc,
+            area,
             early_stop_count,
             sort_by_distance,
+            sort_by_route: false,
+            nearest: None,
+            min_distance: 0.0,
+            excluded: Vec::new(),
+            jobs: 1,
             verbose,
             cache,
+            cache_check: CacheCheck::default(),
+            sidecar_precedence: Precedence::default(),
+            time_window: TimeWindow::default(),
+            exiftool_program: OsString::from("exiftool"),
+            exiftool: Mutex::new(Vec::new()),
+            exiftool_failure: OnceLock::new(),
+        }
+    }
+
+    /// Choose whether XMP sidecar or embedded coordinates win
+    pub fn sidecar_precedence(mut self, precedence: Precedence) -> Searcher {
+        self.sidecar_precedence = precedence;
+        self
+    }
+
+    /// Select only photos taken within the window
+    pub fn time_window(mut self, time_window: TimeWindow) -> Searcher {
+        self.time_window = time_window;
+        self
+    }
+
+    /// Order results from the start to the end of the route in corridor
+    /// searches
+    pub fn sort_by_route(mut self, sort_by_route: bool) -> Searcher {
+        self.sort_by_route = sort_by_route;
+        self
+    }
+
+    /// Keep only the `k` selected photos closest to the target
+    pub fn nearest(mut self, k: Option<usize>) -> Searcher {
+        self.nearest = k;
+        self
+    }
+
+    /// Replace the area given at construction, e.g. once it is known from a
+    /// photo read by this searcher
+    pub fn area(mut self, area: Area) -> Searcher {
+        self.area = area;
+        self
+    }
+
+    /// Never select this file, such as the photo a search is centered on
+    pub fn exclude(mut self, path: &Path) -> Searcher {
+        self.excluded
+            .push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+        self
+    }
+
+    /// Exclude photos closer than this many meters, e.g. to find trips
+    /// away from home
+    pub fn min_distance(mut self, min_distance: f64) -> Searcher {
+        self.min_distance = min_distance;
+        self
+    }
+
+    /// Filter paths from a directory walk or any other source. With
+    /// `nearest` the results are the closest ones sorted by distance.
+    ///
+    /// A non-negative `early_stop_count` stops pulling paths once that many
+    /// photos are selected, so sorting and `nearest` only consider the first
+    /// matches found rather than the best ones overall.
+    pub fn search<I, S>(&self, paths: I) -> Vec<FilterResult>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str> + Sync,
+    {
+        let limit = usize::try_from(self.early_stop_count).unwrap_or(usize::MAX);
+        // Scanned one by one if threads cannot be started
+        let pool = match self.jobs {
+            0 | 1 => None,
+            _ => self.thread_pool().map_err(|e| self.warn(&e)).ok(),
+        };
+        let results: Box<dyn Iterator<Item = FilterResult>> = if let Some(pool) = pool {
+            Box::new(self.filter_in_parallel(pool, paths.into_iter()))
+        } else {
+            Box::new(
+                paths
+                    .into_iter()
+                    .filter_map(|path| self.filter_by_path_str(path.as_ref())),
+            )
+        };
+        let results = results.take(limit);
+        let found = match self.nearest {
+            Some(k) => {
+                // Max-heap holding the k best so far, the farthest on top
+                // Capacity is capped, as K may be far more than the matches
+                let mut heap = BinaryHeap::with_capacity(k.min(1024).saturating_add(1));
+                for result in results {
+                    heap.push(ByDistance(result));
+                    if heap.len() > k {
+                        heap.pop();
+                    }
+                }
+                heap.into_sorted_vec()
+                    .into_iter()
+                    .map(|ByDistance(result)| result)
+                    .collect()
+            }
+            None => results.collect(),
+        };
+        if let Err(e) = self.cache.flush() {
+            self.warn(&e);
         }
+        match self.cache.evict() {
+            Ok(Some(report)) => self.user_msg(&format!(
+                "Pruned {} cache entries over the limits",
+                report.removed.len()
+            )),
+            Ok(None) => {}
+            Err(e) => self.warn(&e),
+        }
+        found
+    }
+
+    /// Choose how cached metadata is checked against the file
+    pub fn cache_check(mut self, cache_check: CacheCheck) -> Searcher {
+        self.cache_check = cache_check;
+        self
+    }
+
+    /// Run this exiftool instead of the one in `PATH`
+    pub fn exiftool_program(mut self, program: impl Into<OsString>) -> Searcher {
+        self.exiftool_program = program.into();
+        self
+    }
+
+    /// Read metadata of up to this many files in parallel
+    pub fn jobs(mut self, jobs: usize) -> Searcher {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    fn thread_pool(&self) -> Result<rayon::ThreadPool> {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(self.jobs)
+            .build()
+            .map_err(|source| Error::Threads {
+                jobs: self.jobs,
+                source,
+            })
+    }
+
+    // Files are taken in batches and the results of each batch are kept in
+    // input order, so the output is the same as when scanning sequentially.
+    // An early stop still finishes the batch it happens in.
+    fn filter_in_parallel<'a, I, S>(
+        &'a self,
+        pool: rayon::ThreadPool,
+        paths: I,
+    ) -> impl Iterator<Item = FilterResult> + 'a
+    where
+        I: Iterator<Item = S> + 'a,
+        S: AsRef<str> + Sync,
+    {
+        let batch_size = self.jobs * BATCH_PER_JOB;
+        let mut paths = paths.fuse();
+        iter::from_fn(move || {
+            let batch: Vec<S> = paths.by_ref().take(batch_size).collect();
+            if batch.is_empty() {
+                return None;
+            }
+            Some(pool.install(|| {
+                batch
+                    .par_iter()
+                    .filter_map(|path| self.filter_by_path_str(path.as_ref()))
+                    .collect::<Vec<_>>()
+            }))
+        })
+        .flatten()
     }
 
+    /// Filter one file, warning about it rather than failing when it cannot
+    /// be read
     pub fn filter_by_path_str(&self, path_str: &str) -> Option<FilterResult> {
         let path = Path::new(&path_str);
-        self.user_msg(&format!("{}", path.to_str().unwrap()));
+        self.user_msg(path_str);
+        if self.is_excluded(path) {
+            return None;
+        }
 
         let filter_result = match self.filter_file(path) {
-            Some(result) => result,
-            None => {
+            Ok(Some(result)) => result,
+            Ok(None) => {
                 self.user_msg(&format!(
                     "Skipping {} because no location info is found",
-                    path.to_str().unwrap()
+                    path_str
                 ));
                 return None;
             }
+            Err(e) => {
+                self.warn(&e);
+                return None;
+            }
         };
         if filter_result.selected {
             self.user_msg(&format!(
@@ -69,8 +318,16 @@ impl Searcher {
         if self.sort_by_distance {
             found.sort_by(|a, b| {
                 a.distance
-                    .partial_cmp(&b.distance)
-                    .unwrap()
+                    .total_cmp(&b.distance)
+                    .then_with(|| a.path.to_string_lossy().cmp(&b.path.to_string_lossy()))
+            });
+        }
+        if self.sort_by_route {
+            found.sort_by(|a, b| {
+                let a_position = a.route_position.unwrap_or(f64::INFINITY);
+                let b_position = b.route_position.unwrap_or(f64::INFINITY);
+                a_position
+                    .total_cmp(&b_position)
                     .then_with(|| a.path.to_string_lossy().cmp(&b.path.to_string_lossy()))
             });
         }
@@ -78,85 +335,292 @@ impl Searcher {
         self.user_msg(&format!("Found {} images", found.len()));
         for f in found.iter() {
             self.user_msg(&format!("{}\t{}", f.distance, f.path.to_string_lossy()));
+            // Matched targets are marked, as only those selected the photo
+            for target in f.targets.iter() {
+                let mark = if target.matched { "\tmatched" } else { "" };
+                self.user_msg(&format!("\t{}\t{}{}", target.distance, target.name, mark));
+            }
         }
         for f in found.iter() {
             println!("{}", f.path.to_string_lossy());
         }
     }
 
-    fn filter_file(&self, path: &Path) -> Option<FilterResult> {
-        let ext = path.extension().unwrap().to_ascii_lowercase();
+    /// Read location and time of a photo the same way as when filtering,
+    /// through the cache
+    pub fn read_photo(&self, path: &Path) -> Result<PhotoMetadata> {
+        Ok(self
+            .read_record(path)?
+            .with_precedence(self.sidecar_precedence))
+    }
 
+    // Embedded and sidecar coordinates are cached apart, so that precedence
+    // is applied per search
+    fn read_record(&self, path: &Path) -> Result<PhotoMetadata> {
         // Read from cache or file
         let key = self.path_to_key(path);
-        let path_str = path.to_string_lossy();
-        let cache_read: Option<PhotoMetadata> = self.cache.read_into(&key);
-        let metadata = match cache_read {
-            Some(metadata) => metadata,
-            // Cache miss
-            None => {
-                self.user_msg(&format!("Exif cache miss"));
-                let metadata = match ext.to_str() {
-                    Some("jpg") => match read_exif_kamadak(path) {
-                        Ok(Some(coords)) => PhotoMetadata {
-                            coordinates: Some(coords),
-                        },
-                        Ok(None) => {
-                            self.user_msg(&format!(
-                                "Found no coordinates by primary parser in {}",
-                                path_str
-                            ));
-                            PhotoMetadata::default()
-                        }
-                        // FIXME: Log error msg
-                        Err(_) => match read_exif_exiftool(path) {
-                            Some(coords) => PhotoMetadata {
-                                coordinates: Some(coords),
-                            },
-                            _ => {
-                                self.user_msg(&format!(
-                                    "Found no coordinates by secondary parser in {}",
-                                    path_str
-                                ));
-                                PhotoMetadata::default()
-                            }
-                        },
-                    },
-                    Some("mp4") => match read_exif_exiftool(path) {
-                        Some(coords) => PhotoMetadata {
-                            coordinates: Some(coords),
-                        },
-                        _ => {
-                            self.user_msg(&format!(
-                                "Found no coordinates by secondary parser {}",
-                                path_str
-                            ));
-                            PhotoMetadata::default()
-                        }
-                    },
-                    _ => {
-                        self.user_msg(&format!("Unsupported type for {}", path_str));
-                        PhotoMetadata::default()
-                    }
+        let (record, exists) = match self.cache.read(&key) {
+            Ok(record) => {
+                let exists = record.is_some();
+                (record, exists)
+            }
+            // Torn entries, as older versions wrote them in place, are
+            // read again and replaced
+            Err(e @ Error::Json { .. }) => {
+                self.warn(&e);
+                (None, true)
+            }
+            Err(e) => return Err(e),
+        };
+        let current = record.as_ref().is_some_and(PhotoMetadata::is_current);
+        let cached: Option<PhotoMetadata> =
+            record.and_then(|record| serde_json::from_value(record).ok());
+        if self.cache_check == CacheCheck::Trust {
+            // Older records are read again, unless the file is gone
+            match cached {
+                Some(metadata) if current || !path.exists() => return Ok(metadata),
+                _ => {}
+            }
+        }
+        // Stamped before reading, so that changes while reading show next time
+        let with_hash = self.cache_check == CacheCheck::Hash;
+        let stamp = FileStamp::of(path, with_hash).map_err(Error::io(path))?;
+        let dir_modified = photo_metadata::dir_modified(path);
+        let sidecar_files = match &cached {
+            Some(metadata) if dir_modified.is_some() && metadata.dir_modified == dir_modified => {
+                self.restamp_sidecars(path, &metadata.sidecar_files, with_hash)
+            }
+            _ => None,
+        }
+        .unwrap_or_else(|| self.stamp_sidecars(path, with_hash));
+        match cached {
+            Some(metadata) if current && metadata.is_stamped(&stamp, &sidecar_files) => {
+                let unhashed = metadata
+                    .file
+                    .as_ref()
+                    .is_some_and(|file| file.hash.is_none());
+                if !(with_hash && unhashed) {
+                    return Ok(metadata);
+                }
+                // Records stamped without a hash get one instead of being read
+                let metadata = PhotoMetadata {
+                    file: Some(stamp),
+                    sidecar_files,
+                    dir_modified,
+                    ..metadata
                 };
-                self.cache.write_from(&key, &metadata);
-                metadata
+                if let Err(e) = self.cache.replace_from(&key, &metadata) {
+                    self.warn(&e);
+                }
+                return Ok(metadata);
             }
+            _ if exists => self.user_msg("Exif cache outdated"),
+            _ => self.user_msg("Exif cache miss"),
+        }
+        let metadata = PhotoMetadata {
+            file: Some(stamp),
+            sidecar_files,
+            dir_modified,
+            path: std::path::absolute(path).ok(),
+            ..self.read_metadata(path)?
         };
+        let written = if exists {
+            self.cache.replace_from(&key, &metadata)
+        } else {
+            self.cache.write_from(&key, &metadata)
+        };
+        // The photo was read fine even if it cannot be cached
+        if let Err(e) = written {
+            self.warn(&e);
+        }
+        Ok(metadata)
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        if self.excluded.is_empty() {
+            return false;
+        }
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.excluded.contains(&path)
+    }
+
+    /// Distances of a photo from the area, `None` if it has no location
+    pub fn filter_file(&self, path: &Path) -> Result<Option<FilterResult>> {
+        let metadata = self.read_photo(path)?;
 
         // Filter by distance
-        match metadata.coordinates {
+        Ok(match metadata.coordinates {
             Some(coords) => {
-                let dist = compute_distance(self.target_loc, coords);
+                let dist = self.area.distance(coords);
+                let targets = self.area.target_distances(coords, self.min_distance);
+                // Each of several targets has its own annulus
+                let in_area = if targets.is_empty() {
+                    self.area.contains(coords, dist) && dist >= self.min_distance
+                } else {
+                    targets.iter().any(|target| target.matched)
+                };
 
                 Some(FilterResult {
                     path: path.to_path_buf(),
-                    selected: dist <= self.radius,
+                    selected: in_area && self.time_window.contains(metadata.taken_at.as_ref()),
                     distance: dist,
+                    route_position: self.area.route_position(coords),
+                    targets,
+                    taken_at: metadata.taken_at,
                 })
             }
             _ => None,
+        })
+    }
+
+    // Capture time always comes from the file itself, while sidecar
+    // coordinates are kept for precedence to choose from
+    fn read_metadata(&self, path: &Path) -> Result<PhotoMetadata> {
+        Ok(PhotoMetadata {
+            sidecar: self.read_sidecars(path),
+            ..self.read_embedded(path)?
+        })
+    }
+
+    // Kept by name, as sidecars are next to the file
+    fn stamp_sidecars(&self, path: &Path, with_hash: bool) -> Vec<(PathBuf, FileStamp)> {
+        SIDECAR_TYPES
+            .iter()
+            .filter_map(|(_, find, _)| {
+                let sidecar = find(path)?;
+                let stamp = FileStamp::of(&sidecar, with_hash).ok()?;
+                Some((PathBuf::from(sidecar.file_name()?), stamp))
+            })
+            .collect()
+    }
+
+    // Only the sidecars found before, while the directory is unchanged.
+    // `None` if one is gone, as the directory then changed after all.
+    fn restamp_sidecars(
+        &self,
+        path: &Path,
+        sidecar_files: &[(PathBuf, FileStamp)],
+        with_hash: bool,
+    ) -> Option<Vec<(PathBuf, FileStamp)>> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        sidecar_files
+            .iter()
+            .map(|(name, _)| {
+                let stamp = FileStamp::of(&dir.join(name), with_hash).ok()?;
+                Some((name.clone(), stamp))
+            })
+            .collect()
+    }
+
+    // XMP written by photo editors is more likely to reflect corrections than
+    // Takeout JSON, so it is tried first
+    fn read_sidecars(&self, path: &Path) -> Option<((f64, f64), MetadataSource)> {
+        SIDECAR_TYPES.iter().find_map(|(source, find, read)| {
+            let sidecar = find(path)?;
+            match read(&sidecar) {
+                Ok(Some(coords)) => Some((coords, *source)),
+                Ok(None) => None,
+                Err(e) => {
+                    self.user_msg(&format!(
+                        "Failed to read sidecar {}: {}",
+                        sidecar.to_string_lossy(),
+                        e
+                    ));
+                    None
+                }
+            }
+        })
+    }
+
+    fn read_embedded(&self, path: &Path) -> Result<PhotoMetadata> {
+        let mut metadata = match format::sniff_file(path).map_err(Error::io(path))? {
+            Some(Format::QuickTime) => {
+                self.read_with_fallback(path, quicktime::read_metadata(path))?
+            }
+            Some(Format::Cr3) => self.read_with_fallback(path, raw::read_cr3_metadata(path))?,
+            Some(format) => self.read_with_fallback(path, read_exif_kamadak(path, format))?,
+            None => {
+                self.user_msg(&format!("Unsupported type for {}", path.to_string_lossy()));
+                PhotoMetadata::default()
+            }
+        };
+        if metadata.coordinates.is_some() {
+            metadata.source = Some(MetadataSource::Embedded);
+        }
+        Ok(metadata)
+    }
+
+    // Fall back to exiftool only if the primary parser fails
+    fn read_with_fallback<E: fmt::Display>(
+        &self,
+        path: &Path,
+        primary: Result<PhotoMetadata, E>,
+    ) -> Result<PhotoMetadata> {
+        let path_str = path.to_string_lossy();
+        match primary {
+            Ok(metadata) => {
+                if metadata.coordinates.is_none() {
+                    self.user_msg(&format!(
+                        "Found no coordinates by primary parser in {}",
+                        path_str
+                    ));
+                }
+                Ok(metadata)
+            }
+            Err(e) => {
+                self.user_msg(&format!(
+                    "Failed to read {} by primary parser: {}",
+                    path_str, e
+                ));
+                match self.read_exif_exiftool(path)? {
+                    Some(metadata) => Ok(metadata),
+                    None => {
+                        self.user_msg(&format!(
+                            "Found no coordinates by secondary parser in {}",
+                            path_str
+                        ));
+                        Ok(PhotoMetadata::default())
+                    }
+                }
+            }
+        }
+    }
+
+    // Much slower than using rust lib. Each thread takes an idle session or
+    // starts one, so there are at most as many as jobs. A session that failed
+    // is dropped rather than returned, while failing to start one is
+    // remembered, e.g. when exiftool is not installed.
+    fn read_exif_exiftool(&self, path: &Path) -> Result<Option<PhotoMetadata>> {
+        let exiftool_error = |source| Error::Exiftool {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some((kind, message)) = self.exiftool_failure.get() {
+            return Err(exiftool_error(io::Error::new(*kind, message.clone())));
         }
+        let idle = self
+            .exiftool
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop();
+        let mut session = match idle {
+            Some(session) => session,
+            None => ExifTool::spawn(&self.exiftool_program).map_err(|e| {
+                let _ = self.exiftool_failure.set((e.kind(), e.to_string()));
+                exiftool_error(e)
+            })?,
+        };
+        let value = session.read_json(path).map_err(exiftool_error)?;
+        self.exiftool
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(session);
+        Ok(value.as_ref().and_then(exiftool_metadata))
+    }
+
+    // Unlike other messages, failures are shown without --verbose too
+    fn warn(&self, error: &Error) {
+        eprintln!("Warning: {}", error);
     }
 
     fn user_msg(&self, msg: &str) {
@@ -172,126 +636,309 @@ impl Searcher {
     }
 }
 
-pub fn visit_paths(src_root: &str) -> impl Iterator<Item = String> {
-    let root_path = Path::new(src_root);
-    globwalk::glob(root_path.join("**/*.{jpg,mp4}").to_str().unwrap())
-        .unwrap()
-        .map(|item| item.unwrap())
-        .map(|item| item.into_path().to_string_lossy().to_string())
+type FindFn = fn(&Path) -> Option<PathBuf>;
+type ReadFn = fn(&Path) -> io::Result<Option<(f64, f64)>>;
+
+/// Sidecar files in the order they are tried, both when read and stamped
+const SIDECAR_TYPES: [(MetadataSource, FindFn, ReadFn); 2] = [
+    (MetadataSource::Xmp, xmp::find_sidecar, xmp::read_location),
+    (
+        MetadataSource::Takeout,
+        takeout::find_sidecar,
+        takeout::read_location,
+    ),
+];
+
+/// Photos with any of the default extensions under a directory
+pub fn visit_paths(src_root: &str) -> Result<impl Iterator<Item = Result<String>>> {
+    Walker::new(&[src_root]).paths()
 }
 
 pub struct FilterResult {
     pub path: PathBuf,
     pub distance: f64,
+    /// Meters from the start of the route in corridor searches
+    pub route_position: Option<f64>,
+    /// Distance to each target when searching around several
+    pub targets: Vec<TargetDistance>,
+    pub taken_at: Option<CaptureTime>,
     selected: bool,
-    // add time info
 }
 
-// Much slower than using rust lib
-fn read_exif_exiftool(path: &Path) -> Option<(f64, f64)> {
-    let output = Command::new("exiftool")
-        .arg("-json")
-        .arg(path)
-        .output()
-        .unwrap();
-    let json = String::from_utf8_lossy(&output.stdout);
-    // println!("exif out: {}", &json);
-    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
-    let lat: String;
-    if let Some(lat_str) = value[0]["GPSLatitude"].as_str() {
-        lat = str::replace(lat_str, " deg", "°");
-    } else {
+// Ordered by distance, then path for stable results
+struct ByDistance(FilterResult);
+
+impl Ord for ByDistance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .distance
+            .total_cmp(&other.0.distance)
+            .then_with(|| self.0.path.cmp(&other.0.path))
+    }
+}
+
+impl PartialOrd for ByDistance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ByDistance {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ByDistance {}
+
+fn exiftool_metadata(value: &serde_json::Value) -> Option<PhotoMetadata> {
+    let coordinates = exiftool_coords(value);
+    let taken_at = exiftool_capture_time(value);
+    if coordinates.is_none() && taken_at.is_none() {
         return None;
     }
-    let lon = str::replace(
-        value[0]["GPSLongitude"]
-            .as_str()
-            .expect("found no longitude"),
-        " deg",
-        "°",
-    );
+    Some(PhotoMetadata {
+        coordinates,
+        taken_at,
+        ..PhotoMetadata::default()
+    })
+}
+
+fn exiftool_coords(value: &serde_json::Value) -> Option<(f64, f64)> {
+    let lat = str::replace(value["GPSLatitude"].as_str()?, " deg", "°");
+    let lon = str::replace(value["GPSLongitude"].as_str()?, " deg", "°");
 
-    let lat_f: f64 = latlon::parse_lat(lat).unwrap();
-    let lon_f: f64 = latlon::parse_lng(lon).unwrap();
+    let lat_f: f64 = latlon::parse_lat(lat).ok()?;
+    let lon_f: f64 = latlon::parse_lng(lon).ok()?;
     Some((lat_f, lon_f))
 }
 
-fn read_exif_kamadak(path: &Path) -> Result<Option<(f64, f64)>, exif::Error> {
-    let file = std::fs::File::open(path).unwrap();
-    let mut bufreader = std::io::BufReader::new(&file);
-    let exifreader = exif::Reader::new();
-    let exif = exifreader.read_from_container(&mut bufreader)?;
+// QuickTime CreationDate carries an offset, while DateTimeOriginal may have
+// it separately
+fn exiftool_capture_time(value: &serde_json::Value) -> Option<CaptureTime> {
+    let original = value["DateTimeOriginal"].as_str().and_then(|datetime| {
+        CaptureTime::from_iso8601(datetime).map(|time| CaptureTime {
+            offset: time
+                .offset
+                .or_else(|| value["OffsetTimeOriginal"].as_str().and_then(parse_offset)),
+            ..time
+        })
+    });
+    let local = original.or_else(|| {
+        value["CreationDate"]
+            .as_str()
+            .and_then(CaptureTime::from_iso8601)
+    });
+    let gps_utc = value["GPSDateTime"]
+        .as_str()
+        .and_then(CaptureTime::from_iso8601)
+        .map(|time| time.local);
+    CaptureTime::merge(local, gps_utc)
+}
 
-    // Latitude
-    let lat_ref = match &exif.get_field(exif::Tag::GPSLatitudeRef, exif::In::PRIMARY) {
-        Some(field) => field.display_value(),
-        _ => return Ok(None),
+fn read_exif_kamadak(path: &Path, format: Format) -> Result<PhotoMetadata, exif::Error> {
+    let exif = match format {
+        Format::Orf | Format::Rw2 | Format::Raf => raw::read_exif(path, format),
+        _ => {
+            let file = std::fs::File::open(path)?;
+            let mut bufreader = std::io::BufReader::new(&file);
+            let exifreader = exif::Reader::new();
+            exifreader.read_from_container(&mut bufreader)
+        }
     };
-    let lat = match &exif
-        .get_field(exif::Tag::GPSLatitude, exif::In::PRIMARY)
-        .unwrap()
-        .value
-    {
-        exif::Value::Rational(lat_rational) => coord_rational_to_f64(lat_rational, lat_ref),
-        exif::Value::SRational(lat_rational) => coord_srational_to_f64(lat_rational, lat_ref),
-        _ => return Ok(None),
+    let exif = match exif {
+        Ok(exif) => exif,
+        // Not a failure to fall back from, as screenshots and exported
+        // images often have no Exif at all
+        Err(exif::Error::NotFound(_)) => return Ok(PhotoMetadata::default()),
+        Err(e) => return Err(e),
+    };
+    Ok(PhotoMetadata {
+        coordinates: coords_from_exif(&exif, |tag| tag),
+        taken_at: CaptureTime::merge(
+            original_time_from_exif(&exif, |tag| tag),
+            gps_time_from_exif(&exif, |tag| tag),
+        ),
+        ..PhotoMetadata::default()
+    })
+}
+
+// Tags are mapped for containers storing the GPS IFD outside of its usual
+// place, e.g. as IFD0 of a separate TIFF
+fn coords_from_exif(
+    exif: &exif::Exif,
+    map_tag: impl Fn(exif::Tag) -> exif::Tag,
+) -> Option<(f64, f64)> {
+    let get_field = |tag| exif.get_field(map_tag(tag), exif::In::PRIMARY);
+
+    // Latitude
+    let lat_ref = ascii_value(get_field(exif::Tag::GPSLatitudeRef)?)?;
+    let lat = match &get_field(exif::Tag::GPSLatitude)?.value {
+        exif::Value::Rational(lat_rational) => coord_rational_to_f64(lat_rational, lat_ref)?,
+        exif::Value::SRational(lat_rational) => coord_srational_to_f64(lat_rational, lat_ref)?,
+        _ => return None,
     };
 
     // Longitude
-    let lon_ref = match &exif.get_field(exif::Tag::GPSLongitudeRef, exif::In::PRIMARY) {
-        Some(field) => field.display_value(),
-        _ => return Ok(None),
+    let lon_ref = ascii_value(get_field(exif::Tag::GPSLongitudeRef)?)?;
+    let lon = match &get_field(exif::Tag::GPSLongitude)?.value {
+        exif::Value::Rational(lon_rational) => coord_rational_to_f64(lon_rational, lon_ref)?,
+        exif::Value::SRational(lon_rational) => coord_srational_to_f64(lon_rational, lon_ref)?,
+        _ => return None,
     };
-    let lon = match &exif
-        .get_field(exif::Tag::GPSLongitude, exif::In::PRIMARY)
-        .unwrap()
-        .value
-    {
-        exif::Value::Rational(lon_rational) => coord_rational_to_f64(lon_rational, lon_ref),
-        exif::Value::SRational(lon_rational) => coord_srational_to_f64(lon_rational, lon_ref),
-        _ => return Ok(None),
+
+    Some((lat, lon))
+}
+
+fn original_time_from_exif(
+    exif: &exif::Exif,
+    map_tag: impl Fn(exif::Tag) -> exif::Tag,
+) -> Option<CaptureTime> {
+    let get_field = |tag| exif.get_field(map_tag(tag), exif::In::PRIMARY);
+
+    let datetime = ascii_value(get_field(exif::Tag::DateTimeOriginal)?)?;
+    let offset = get_field(exif::Tag::OffsetTimeOriginal).and_then(ascii_value);
+    CaptureTime::from_exif(&datetime, offset.as_deref())
+}
+
+fn gps_time_from_exif(
+    exif: &exif::Exif,
+    map_tag: impl Fn(exif::Tag) -> exif::Tag,
+) -> Option<NaiveDateTime> {
+    let get_field = |tag| exif.get_field(map_tag(tag), exif::In::PRIMARY);
+
+    let date = ascii_value(get_field(exif::Tag::GPSDateStamp)?)?;
+    let date = NaiveDate::parse_from_str(date.trim(), "%Y:%m:%d").ok()?;
+    let seconds = match &get_field(exif::Tag::GPSTimeStamp)?.value {
+        exif::Value::Rational(time) if time.len() == 3 => {
+            time[0].to_f64() * 3600.0 + time[1].to_f64() * 60.0 + time[2].to_f64()
+        }
+        _ => return None,
     };
+    // Malformed files may hold huge or non-finite times
+    if !seconds.is_finite() || !(0.0..86400.0).contains(&seconds) {
+        return None;
+    }
+    let time = TimeDelta::try_milliseconds((seconds * 1000.0) as i64)?;
+    date.and_time(NaiveTime::MIN).checked_add_signed(time)
+}
 
-    Ok(Some((lat, lon)))
+fn ascii_value(field: &exif::Field) -> Option<String> {
+    match &field.value {
+        exif::Value::Ascii(values) => Some(String::from_utf8_lossy(values.first()?).to_string()),
+        _ => None,
+    }
 }
 
 // FIXME: refactor rational vs srational
-fn coord_rational_to_f64<T: ToString>(coord_rational: &Vec<exif::Rational>, coord_ref: T) -> f64 {
-    let sign = if coord_ref.to_string() == "W" { -1 } else { 1 };
-    sign as f64
-        * (coord_rational[0].to_f64()
-            + coord_rational[1].to_f64() / 60.0
-            + coord_rational[2].to_f64() / 60.0 / 60.0)
+// Degrees, minutes and seconds, `None` when a malformed file has fewer
+fn coord_rational_to_f64<T: ToString>(
+    coord_rational: &[exif::Rational],
+    coord_ref: T,
+) -> Option<f64> {
+    let [degrees, minutes, seconds, ..] = coord_rational else {
+        return None;
+    };
+    let sign = match coord_ref.to_string().as_str() {
+        "S" | "W" => -1,
+        _ => 1,
+    };
+    Some(
+        sign as f64 * (degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 60.0 / 60.0),
+    )
 }
 
-fn coord_srational_to_f64<T: ToString>(coord_rational: &Vec<exif::SRational>, coord_ref: T) -> f64 {
-    let sign = if coord_ref.to_string() == "W" { -1 } else { 1 };
-    sign as f64
-        * (coord_rational[0].to_f64()
-            + coord_rational[1].to_f64() / 60.0
-            + coord_rational[2].to_f64() / 60.0 / 60.0)
+fn coord_srational_to_f64<T: ToString>(
+    coord_rational: &[exif::SRational],
+    coord_ref: T,
+) -> Option<f64> {
+    let [degrees, minutes, seconds, ..] = coord_rational else {
+        return None;
+    };
+    let sign = match coord_ref.to_string().as_str() {
+        "S" | "W" => -1,
+        _ => 1,
+    };
+    Some(
+        sign as f64 * (degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 60.0 / 60.0),
+    )
 }
 
 fn compute_distance((lat0, lon0): (f64, f64), (lat1, lon1): (f64, f64)) -> f64 {
     let loc0 = Point::new(lon0, lat0);
     let loc1 = Point::new(lon1, lat1);
-    let dist = Haversine::distance(loc0, loc1);
-    dist
+    Haversine::distance(loc0, loc1)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::fs;
+    use std::{cell::Cell, fs};
 
     use super::*;
     use tempfile::tempdir;
 
+    #[test]
+    fn convert_coordinate_rationals() {
+        let rationals: Vec<exif::Rational> = vec![(40, 1).into(), (30, 1).into(), (36, 1).into()];
+        assert_eq!(coord_rational_to_f64(&rationals, "S"), Some(-40.51));
+        assert_eq!(coord_rational_to_f64(&rationals[..2], "N"), None);
+        let srationals: Vec<exif::SRational> = vec![(40, 1).into(), (30, 1).into()];
+        assert_eq!(coord_srational_to_f64(&srationals, "N"), None);
+        assert_eq!(coord_srational_to_f64(&[], "N"), None);
+    }
+
+    #[test]
+    fn reject_malformed_gps_time() {
+        let read_gps_time = |time: Vec<exif::Rational>| {
+            let fields = [
+                (
+                    exif::Tag::GPSDateStamp,
+                    exif::Value::Ascii(vec![b"2024:03:15".to_vec()]),
+                ),
+                (exif::Tag::GPSTimeStamp, exif::Value::Rational(time)),
+            ]
+            .map(|(tag, value)| exif::Field {
+                tag,
+                ifd_num: exif::In::PRIMARY,
+                value,
+            });
+            let mut writer = exif::experimental::Writer::new();
+            for field in fields.iter() {
+                writer.push_field(field);
+            }
+            let mut buf = std::io::Cursor::new(Vec::new());
+            writer.write(&mut buf, false).unwrap();
+            let exif = exif::Reader::new().read_raw(buf.into_inner()).unwrap();
+            gps_time_from_exif(&exif, |tag| tag)
+        };
+
+        assert_eq!(
+            read_gps_time(vec![(9, 1).into(), (20, 1).into(), (28, 1).into()]),
+            NaiveDate::from_ymd_opt(2024, 3, 15)
+                .unwrap()
+                .and_hms_opt(9, 20, 28)
+        );
+        assert_eq!(
+            read_gps_time(vec![(u32::MAX, 1).into(), (0, 1).into(), (0, 1).into()]),
+            None
+        );
+        assert_eq!(
+            read_gps_time(vec![(9, 0).into(), (0, 1).into(), (0, 1).into()]),
+            None
+        );
+    }
+
     #[test]
     fn compare_read_exif() {
         let path = Path::new("samples/sample.jpg");
 
-        let loc_exiftool = read_exif_exiftool(path).unwrap();
-        let loc_kamadak = read_exif_kamadak(path).unwrap().unwrap();
+        let mut exiftool = ExifTool::spawn("exiftool".as_ref()).unwrap();
+        let value = exiftool.read_json(path).unwrap().unwrap();
+        let loc_exiftool = exiftool_metadata(&value).unwrap().coordinates.unwrap();
+        let loc_kamadak = read_exif_kamadak(path, Format::Jpeg)
+            .unwrap()
+            .coordinates
+            .unwrap();
 
         assert_eq!(loc_exiftool, loc_kamadak);
     }
@@ -307,6 +954,857 @@ mod tests {
         test_filter_path_with_photo("sample-no-coords.jpg");
     }
 
+    #[test]
+    fn filter_video_with_location() {
+        let temp_dir = tempdir().unwrap();
+        let exif_cache = Cache::new(&temp_dir.path().join("exif")).unwrap();
+        let searcher = Searcher::new(10000.0, (37.33, -122.03), 10, true, true, exif_cache);
+
+        let video_path = temp_dir.path().join("video.mp4");
+        fs::write(
+            &video_path,
+            quicktime::tests::make_mp4("+37.3317-122.0307/"),
+        )
+        .unwrap();
+        let result = searcher
+            .filter_by_path_str(video_path.to_str().unwrap())
+            .unwrap();
+
+        assert_eq!(result.path, video_path);
+        assert!(result.distance < 300.0);
+    }
+
+    #[test]
+    fn filter_images_by_content() {
+        let tiff = make_exif_tiff((37.3317, -122.0307), false);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&(tiff.len() as u32).to_be_bytes());
+        png.extend_from_slice(b"eXIf");
+        png.extend_from_slice(&tiff);
+        png.extend_from_slice(&[0; 4]);
+        png.extend_from_slice(b"\0\0\0\0IEND\xae\x42\x60\x82");
+
+        let mut webp = b"RIFF".to_vec();
+        webp.extend_from_slice(&(tiff.len() as u32 + 12).to_le_bytes());
+        webp.extend_from_slice(b"WEBPEXIF");
+        webp.extend_from_slice(&(tiff.len() as u32).to_le_bytes());
+        webp.extend_from_slice(&tiff);
+
+        let temp_dir = tempdir().unwrap();
+        let exif_cache = Cache::new(&temp_dir.path().join("exif")).unwrap();
+        let searcher = Searcher::new(10000.0, (37.33, -122.03), 10, true, true, exif_cache);
+
+        // Extensions do not match the content on purpose
+        for (filename, contents) in [("image.tif", &tiff), ("image.png", &png), ("image", &webp)] {
+            let path = temp_dir.path().join(filename);
+            fs::write(&path, contents).unwrap();
+            let result = searcher.filter_by_path_str(path.to_str().unwrap());
+
+            assert!(result.unwrap().distance < 300.0, "{}", filename);
+        }
+    }
+
+    #[test]
+    fn filter_heif_image() {
+        use quicktime::tests::make_box;
+
+        // Exif item stored in the meta box itself, after its header offset
+        let exif_item = [
+            b"\0\0\0\x06Exif\0\0".to_vec(),
+            make_exif_tiff((37.3317, -122.0307), false),
+        ]
+        .concat();
+        let infe = make_box(b"infe", b"\x02\0\0\0\0\x01\0\0Exif\0");
+        let iinf = make_box(b"iinf", &[b"\0\0\0\0\0\x01".to_vec(), infe].concat());
+        let mut iloc = b"\x01\0\0\0\x44\0\0\x01\0\x01\0\x01\0\0\0\x01\0\0\0\0".to_vec();
+        iloc.extend_from_slice(&(exif_item.len() as u32).to_be_bytes());
+        let meta = make_box(
+            b"meta",
+            &[
+                b"\0\0\0\0".to_vec(),
+                iinf,
+                make_box(b"iloc", &iloc),
+                make_box(b"idat", &exif_item),
+            ]
+            .concat(),
+        );
+        let heic = [make_box(b"ftyp", b"heic\0\0\0\0mif1heic"), meta].concat();
+
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("image.heic");
+        fs::write(&path, heic).unwrap();
+        assert_eq!(format::sniff_file(&path).unwrap(), Some(Format::Heif));
+
+        let exif_cache = Cache::new(&temp_dir.path().join("exif")).unwrap();
+        let searcher = Searcher::new(10000.0, (37.33, -122.03), 10, true, true, exif_cache);
+        let result = searcher.filter_by_path_str(path.to_str().unwrap());
+
+        assert!(result.unwrap().distance < 300.0);
+    }
+
+    #[test]
+    fn read_exif_southern_western() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("image.tif");
+        fs::write(&path, make_exif_tiff((-33.8568, 151.2153), true)).unwrap();
+
+        let (lat, lon) = read_exif_kamadak(&path, Format::Tiff)
+            .unwrap()
+            .coordinates
+            .unwrap();
+
+        assert!((lat + 33.8568).abs() < 1e-6);
+        assert!((lon - 151.2153).abs() < 1e-6);
+    }
+
+    #[test]
+    fn filter_with_sidecar_precedence() {
+        let temp_dir = tempdir().unwrap();
+        let photo_path = temp_dir.path().join("photo.tif");
+        fs::write(&photo_path, make_exif_tiff((37.3317, -122.0307), false)).unwrap();
+        fs::write(
+            temp_dir.path().join("photo.xmp"),
+            xmp::tests::SIDECAR_ATTRIBUTES,
+        )
+        .unwrap();
+
+        // Both precedences share one cache, filled by the first search
+        let exif_cache = Cache::new(&temp_dir.path().join("exif")).unwrap();
+        let mut searcher = Searcher::new(10000.0, (37.33, -122.03), 10, true, true, exif_cache);
+        for (precedence, source) in [
+            (Precedence::Sidecar, MetadataSource::Xmp),
+            (Precedence::Embedded, MetadataSource::Embedded),
+            (Precedence::Sidecar, MetadataSource::Xmp),
+        ] {
+            searcher = searcher.sidecar_precedence(precedence);
+
+            let metadata = searcher.read_photo(&photo_path).unwrap();
+
+            assert_eq!(metadata.source, Some(source), "{:?}", precedence);
+        }
+    }
+
+    #[test]
+    fn filter_with_sidecar_only() {
+        let temp_dir = tempdir().unwrap();
+        let exif_cache = Cache::new(&temp_dir.path().join("exif")).unwrap();
+        let searcher = Searcher::new(10000.0, (40.44, -79.98), 10, true, true, exif_cache)
+            .sidecar_precedence(Precedence::Embedded);
+
+        // Embedded metadata has no coordinates
+        let photo_path = temp_dir.path().join("photo.tif");
+        fs::write(&photo_path, make_tiff_without_gps()).unwrap();
+        fs::write(
+            temp_dir.path().join("photo.tif.xmp"),
+            xmp::tests::SIDECAR_ATTRIBUTES,
+        )
+        .unwrap();
+        let result = searcher.filter_by_path_str(photo_path.to_str().unwrap());
+
+        assert!(result.unwrap().distance < 1000.0);
+    }
+
+    #[test]
+    fn filter_with_takeout_sidecar() {
+        let temp_dir = tempdir().unwrap();
+        let exif_cache = Cache::new(&temp_dir.path().join("exif")).unwrap();
+        let searcher = Searcher::new(10000.0, (48.85, 2.29), 10, true, true, exif_cache);
+
+        // Takeout strips GPS from the photo itself
+        let photo_path = temp_dir.path().join("IMG_0001(1).jpg");
+        fs::write(&photo_path, make_tiff_without_gps()).unwrap();
+        fs::write(
+            temp_dir.path().join("IMG_0001.jpg(1).json"),
+            takeout::tests::SIDECAR,
+        )
+        .unwrap();
+        let metadata = searcher.read_photo(&photo_path).unwrap();
+
+        assert_eq!(metadata.coordinates, Some((48.8583701, 2.2944813)));
+        assert_eq!(metadata.source, Some(MetadataSource::Takeout));
+    }
+
+    #[test]
+    fn filter_by_time_window() {
+        let temp_dir = tempdir().unwrap();
+        let photo_path = temp_dir.path().join("photo.tif");
+        fs::write(&photo_path, make_exif_tiff((37.3317, -122.0307), false)).unwrap();
+
+        for (after, before, selected) in [
+            ("2024-03-01", "2024-04-01", true),
+            ("2024-03-15T10:21", "2024-04-01", false),
+            // Offset derived from GPS time is compared as instant
+            ("2024-03-15T09:00Z", "2024-03-15T09:30Z", true),
+        ] {
+            let exif_cache = Cache::new(&temp_dir.path().join(after.replace(':', ""))).unwrap();
+            let time_window = TimeWindow {
+                after: Some(after.parse().unwrap()),
+                before: Some(before.parse().unwrap()),
+            };
+            let searcher = Searcher::new(10000.0, (37.33, -122.03), 10, true, true, exif_cache)
+                .time_window(time_window);
+
+            let result = searcher.filter_by_path_str(photo_path.to_str().unwrap());
+
+            assert_eq!(result.is_some(), selected, "{} - {}", after, before);
+        }
+    }
+
+    #[test]
+    fn read_exif_capture_time() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("image.tif");
+        fs::write(&path, make_exif_tiff((37.3317, -122.0307), true)).unwrap();
+
+        let taken_at = read_exif_kamadak(&path, Format::Tiff).unwrap().taken_at;
+
+        assert_eq!(
+            taken_at,
+            CaptureTime::from_exif("2024:03:15 10:20:30", Some("+01:00"))
+        );
+    }
+
+    #[test]
+    fn filter_by_bbox() {
+        let temp_dir = tempdir().unwrap();
+        let photo_path = temp_dir.path().join("photo.tif");
+        fs::write(&photo_path, make_exif_tiff((37.3317, -122.0307), false)).unwrap();
+
+        for (bbox, selected) in [
+            ("-122.1,37.3,-122.0,37.4", true),
+            ("-122.0,37.3,-121.9,37.4", false),
+        ] {
+            let exif_cache = Cache::new(&temp_dir.path().join(bbox)).unwrap();
+            let area = Area::BoundingBox(bbox.parse().unwrap());
+            let searcher = Searcher::with_area(area, 10, true, true, exif_cache);
+
+            let result = searcher.filter_by_path_str(photo_path.to_str().unwrap());
+
+            assert_eq!(result.is_some(), selected, "{}", bbox);
+        }
+    }
+
+    #[test]
+    fn filter_by_polygon() {
+        let temp_dir = tempdir().unwrap();
+        let photo_path = temp_dir.path().join("photo.tif");
+        fs::write(&photo_path, make_exif_tiff((37.3317, -122.0307), false)).unwrap();
+
+        for (wkt, selected) in [
+            (
+                "POLYGON ((-122.1 37.3, -122.0 37.3, -122.0 37.4, -122.1 37.4, -122.1 37.3))",
+                true,
+            ),
+            (
+                "POLYGON ((-122.1 37.3, -122.0 37.3, -122.0 37.4, -122.1 37.4, -122.1 37.3), \
+                 (-122.04 37.33, -122.02 37.33, -122.02 37.34, -122.04 37.34, -122.04 37.33))",
+                false,
+            ),
+        ] {
+            let area_path = temp_dir.path().join("area.wkt");
+            fs::write(&area_path, wkt).unwrap();
+            let exif_cache = Cache::new(&temp_dir.path().join(selected.to_string())).unwrap();
+            let area = Area::Polygon(polygon::read_polygons(&area_path).unwrap());
+            let searcher = Searcher::with_area(area, 10, true, true, exif_cache);
+
+            let result = searcher.filter_by_path_str(photo_path.to_str().unwrap());
+
+            assert_eq!(result.is_some(), selected, "{}", wkt);
+        }
+    }
+
+    #[test]
+    fn filter_along_route() {
+        let temp_dir = tempdir().unwrap();
+        let route = Route::new(vec![(0.0, 0.0), (0.0, 0.01), (0.01, 0.01)]);
+        let mut paths = Vec::new();
+        // Second leg first, then first leg, then too far away
+        for (name, coords) in [
+            ("a.tif", (0.005, 0.0101)),
+            ("b.tif", (0.0005, 0.002)),
+            ("c.tif", (0.005, 0.02)),
+        ] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, make_exif_tiff(coords, false)).unwrap();
+            paths.push(path);
+        }
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let area = Area::Corridor {
+            route,
+            radius: 100.0,
+        };
+        let searcher = Searcher::with_area(area, -1, false, true, exif_cache).sort_by_route(true);
+
+        let mut found: Vec<_> = paths
+            .iter()
+            .filter_map(|path| searcher.filter_by_path_str(path.to_str().unwrap()))
+            .collect();
+        found.sort_by(|a, b| {
+            a.route_position
+                .unwrap()
+                .total_cmp(&b.route_position.unwrap())
+        });
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].path, paths[1]);
+        assert!((found[0].distance - 55.6).abs() < 1.0);
+        assert!((found[0].route_position.unwrap() - 222.4).abs() < 1.0);
+        assert!((found[1].route_position.unwrap() - 1667.9).abs() < 1.0);
+    }
+
+    #[test]
+    fn filter_by_targets() {
+        let temp_dir = tempdir().unwrap();
+        let photo_path = temp_dir.path().join("photo.tif");
+        fs::write(&photo_path, make_exif_tiff((48.8584, 2.2945), false)).unwrap();
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let targets = vec![
+            Target {
+                name: "Big Ben".to_string(),
+                coords: (51.5007, -0.1246),
+            },
+            Target {
+                name: "Eiffel Tower".to_string(),
+                coords: (48.8583, 2.2944),
+            },
+            Target {
+                name: "Trocadero".to_string(),
+                coords: (48.8616, 2.2893),
+            },
+        ];
+        let area = Area::Targets {
+            targets,
+            radius: 1000.0,
+        };
+        let searcher = Searcher::with_area(area, -1, false, true, exif_cache);
+
+        let result = searcher
+            .filter_by_path_str(photo_path.to_str().unwrap())
+            .unwrap();
+
+        let matched: Vec<_> = result
+            .targets
+            .iter()
+            .filter(|target| target.matched)
+            .map(|target| target.name.as_str())
+            .collect();
+        assert_eq!(matched, ["Eiffel Tower", "Trocadero"]);
+        assert!(result.targets[0].distance > 300_000.0);
+        assert!(result.distance < 20.0);
+    }
+
+    #[test]
+    fn search_nearest() {
+        let temp_dir = tempdir().unwrap();
+        let mut paths = Vec::new();
+        for (i, lon) in [0.04, 0.01, 0.05, 0.02, 0.03].iter().enumerate() {
+            let path = temp_dir.path().join(format!("{}.tif", i));
+            fs::write(&path, make_exif_tiff((0.0, *lon), false)).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let searcher =
+            Searcher::new(f64::INFINITY, (0.0, 0.0), -1, false, true, exif_cache).nearest(Some(3));
+
+        let found = searcher.search(&paths);
+
+        let found: Vec<_> = found.iter().map(|f| f.path.to_str().unwrap()).collect();
+        assert_eq!(found, [&paths[1], &paths[3], &paths[4]]);
+
+        let searcher = searcher.nearest(Some(usize::MAX));
+        assert_eq!(searcher.search(&paths).len(), paths.len());
+        assert!(searcher.nearest(Some(0)).search(&paths).is_empty());
+    }
+
+    #[test]
+    fn filter_by_annulus() {
+        let temp_dir = tempdir().unwrap();
+        let mut paths = Vec::new();
+        // About 1.1, 2.2 and 3.3 km east
+        for lon in [0.01, 0.02, 0.03] {
+            let path = temp_dir.path().join(format!("{}.tif", lon));
+            fs::write(&path, make_exif_tiff((0.0, lon), false)).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let searcher =
+            Searcher::new(3000.0, (0.0, 0.0), -1, true, true, exif_cache).min_distance(2000.0);
+
+        let found = searcher.search(&paths);
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path.to_str().unwrap(), paths[1]);
+
+        let searcher = searcher.nearest(Some(1));
+        let found = searcher.search(&paths);
+        assert_eq!(found[0].path.to_str().unwrap(), paths[1]);
+
+        // In the annulus of the first target while in the hole of the second
+        let area = Area::Targets {
+            targets: vec![Target::at((0.0, 0.0)), Target::at((0.0, 0.02))],
+            radius: 3000.0,
+        };
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let searcher = Searcher::with_area(area, -1, true, true, exif_cache).min_distance(2000.0);
+        let found = searcher.search(&paths);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path.to_str().unwrap(), paths[1]);
+        let matched: Vec<_> = found[0].targets.iter().map(|t| t.matched).collect();
+        assert_eq!(matched, [true, false]);
+    }
+
+    #[test]
+    fn search_near_photo() {
+        let temp_dir = tempdir().unwrap();
+        let reference = temp_dir.path().join("reference.tif");
+        let nearby = temp_dir.path().join("nearby.tif");
+        fs::write(&reference, make_exif_tiff((35.6586, 139.7454), false)).unwrap();
+        fs::write(&nearby, make_exif_tiff((35.6590, 139.7450), true)).unwrap();
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let searcher = Searcher::new(100.0, (0.0, 0.0), -1, false, true, exif_cache);
+
+        let metadata = searcher.read_photo(&reference).unwrap();
+        let center = metadata.coordinates.unwrap();
+        let searcher = searcher
+            .area(Area::Circle {
+                center,
+                radius: 100.0,
+            })
+            .exclude(&temp_dir.path().join(".").join("reference.tif"));
+
+        assert!(metadata.taken_at.is_some());
+        let found = searcher.search([reference.to_str().unwrap(), nearby.to_str().unwrap()]);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, nearby);
+    }
+
+    // The fake exiftool is a shell script
+    #[cfg(unix)]
+    #[test]
+    fn fall_back_to_exiftool_session() {
+        let temp_dir = tempdir().unwrap();
+        let program = exiftool::tests::fake_exiftool(temp_dir.path());
+        let mut paths = Vec::new();
+        for name in ["a.jpg", "missing.jpg", "b.jpg"] {
+            // Sniffed as JPEG but unreadable by kamadak-exif
+            let path = temp_dir.path().join(name);
+            fs::write(&path, b"\xff\xd8\xff\xe1\0\0broken").unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        let searcher = Searcher::new(
+            1000.0,
+            (40.4461, -79.9822),
+            -1,
+            false,
+            false,
+            Cache::new(&temp_dir.path().join("cache")).unwrap(),
+        )
+        .exiftool_program(program)
+        .jobs(2);
+
+        let found = searcher.search(&paths);
+
+        let names: Vec<_> = found.iter().map(|f| f.path.file_name().unwrap()).collect();
+        assert_eq!(names, ["a.jpg", "b.jpg"]);
+        assert!(found[0].taken_at.is_some());
+        // Sessions are reused, at most one per job
+        let starts = fs::read_to_string(temp_dir.path().join("starts.log")).unwrap();
+        assert!((1..=2).contains(&starts.lines().count()));
+    }
+
+    // The fake exiftool is a shell script
+    #[cfg(unix)]
+    #[test]
+    fn read_without_exif_or_exiftool() {
+        let temp_dir = tempdir().unwrap();
+        let program = temp_dir.path().join("exiftool");
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let searcher = Searcher::new(1000.0, (0.0, 0.0), -1, false, false, exif_cache)
+            .exiftool_program(&program);
+
+        // A JPEG without Exif is read and cached without exiftool
+        let plain = temp_dir.path().join("plain.jpg");
+        fs::write(&plain, b"\xff\xd8\xff\xd9").unwrap();
+        assert!(searcher.read_photo(&plain).unwrap().coordinates.is_none());
+        let record = searcher.cache.read(&searcher.path_to_key(&plain));
+        assert!(record.unwrap().is_some());
+
+        // Starting exiftool is not tried again once it failed
+        let broken = temp_dir.path().join("broken.jpg");
+        fs::write(&broken, b"\xff\xd8\xff\xe1\0\0broken").unwrap();
+        assert!(matches!(
+            searcher.read_photo(&broken),
+            Err(Error::Exiftool { .. })
+        ));
+        exiftool::tests::fake_exiftool(temp_dir.path());
+        assert!(matches!(
+            searcher.read_photo(&broken),
+            Err(Error::Exiftool { .. })
+        ));
+        assert!(!temp_dir.path().join("starts.log").exists());
+    }
+
+    #[test]
+    fn search_past_unreadable_files() {
+        let temp_dir = tempdir().unwrap();
+        // No extension, as may come from stdin
+        let photo = temp_dir.path().join("photo");
+        fs::write(&photo, make_exif_tiff((0.0, 0.0), true)).unwrap();
+        let missing = temp_dir.path().join("missing.jpg");
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let searcher = Searcher::new(100.0, (0.0, 0.0), -1, false, false, exif_cache);
+
+        assert!(matches!(
+            searcher.filter_file(&missing),
+            Err(Error::Io { path, .. }) if path == missing
+        ));
+        let found = searcher.search([missing.to_str().unwrap(), photo.to_str().unwrap()]);
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, photo);
+    }
+
+    #[test]
+    fn read_photo_again_when_changed() {
+        let temp_dir = tempdir().unwrap();
+        let photo = temp_dir.path().join("photo.tif");
+        let searcher = |cache_check| {
+            let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+            Searcher::new(100.0, (0.0, 0.0), -1, false, false, exif_cache).cache_check(cache_check)
+        };
+        let coords = |searcher: &Searcher| searcher.read_photo(&photo).unwrap().coordinates;
+        let write_photo = |lat| {
+            let modified = fs::metadata(&photo).and_then(|m| m.modified()).ok();
+            fs::write(&photo, make_exif_tiff((lat, 0.0), true)).unwrap();
+            // Same size and time, as when a tool keeps the time of an edited file
+            if let Some(modified) = modified {
+                let file = fs::File::options().write(true).open(&photo).unwrap();
+                file.set_modified(modified).unwrap();
+            }
+        };
+
+        let hash = |searcher: &Searcher| {
+            let record = searcher.cache.read(&searcher.path_to_key(&photo));
+            let metadata: PhotoMetadata = serde_json::from_value(record.unwrap().unwrap()).unwrap();
+            metadata.file.unwrap().hash
+        };
+
+        write_photo(1.0);
+        assert_eq!(coords(&searcher(CacheCheck::Stamp)), Some((1.0, 0.0)));
+        // Switching modes keeps records, which get a hash when needed
+        assert_eq!(hash(&searcher(CacheCheck::Stamp)), None);
+        assert_eq!(coords(&searcher(CacheCheck::Hash)), Some((1.0, 0.0)));
+        assert!(hash(&searcher(CacheCheck::Stamp)).is_some());
+        assert_eq!(coords(&searcher(CacheCheck::Stamp)), Some((1.0, 0.0)));
+        assert!(hash(&searcher(CacheCheck::Stamp)).is_some());
+
+        write_photo(2.0);
+        assert_eq!(coords(&searcher(CacheCheck::Stamp)), Some((1.0, 0.0)));
+        assert_eq!(coords(&searcher(CacheCheck::Hash)), Some((2.0, 0.0)));
+        assert_eq!(coords(&searcher(CacheCheck::Hash)), Some((2.0, 0.0)));
+
+        let file = fs::File::options().write(true).open(&photo).unwrap();
+        file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        drop(file);
+        write_photo(3.0);
+        assert_eq!(coords(&searcher(CacheCheck::Trust)), Some((2.0, 0.0)));
+        assert_eq!(coords(&searcher(CacheCheck::Stamp)), Some((3.0, 0.0)));
+
+        let trusted = searcher(CacheCheck::Trust);
+        let record = trusted.cache.read(&trusted.path_to_key(&photo));
+        let record = record.unwrap().unwrap();
+        assert!(!PhotoMetadata::is_orphan(&record));
+        fs::remove_file(&photo).unwrap();
+        assert!(PhotoMetadata::is_orphan(&record));
+        assert_eq!(coords(&trusted), Some((3.0, 0.0)));
+        assert!(searcher(CacheCheck::Stamp).read_photo(&photo).is_err());
+    }
+
+    #[test]
+    fn read_photo_again_when_cache_entry_corrupt() {
+        let temp_dir = tempdir().unwrap();
+        let photo = temp_dir.path().join("photo.tif");
+        fs::write(&photo, make_exif_tiff((37.3317, -122.0307), false)).unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+
+        for cache_check in [CacheCheck::Trust, CacheCheck::Stamp] {
+            let exif_cache = Cache::new(&cache_dir).unwrap();
+            let searcher = Searcher::new(10000.0, (37.33, -122.03), 10, true, true, exif_cache)
+                .cache_check(cache_check);
+            let coords = searcher.read_photo(&photo).unwrap().coordinates;
+            let entry = fs::read_dir(&cache_dir).unwrap().next().unwrap().unwrap();
+            let contents = fs::read(entry.path()).unwrap();
+            fs::write(entry.path(), &contents[..contents.len() / 2]).unwrap();
+
+            let metadata = searcher.read_photo(&photo).unwrap();
+
+            assert!(coords.is_some());
+            assert_eq!(metadata.coordinates, coords);
+            let record = searcher.cache.read(&searcher.path_to_key(&photo));
+            assert!(record.unwrap().is_some(), "{:?}", cache_check);
+        }
+    }
+
+    // Directories cannot be opened to set their time everywhere
+    #[cfg(unix)]
+    #[test]
+    fn look_for_sidecars_only_when_dir_changed() {
+        let temp_dir = tempdir().unwrap();
+        let photo = temp_dir.path().join("photo.tif");
+        fs::write(&photo, make_exif_tiff((37.3317, -122.0307), false)).unwrap();
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let searcher = Searcher::new(10000.0, (37.33, -122.03), 10, true, true, exif_cache);
+        let source = || searcher.read_photo(&photo).unwrap().source;
+        assert_eq!(source(), Some(MetadataSource::Embedded));
+        let set_dir_modified = |modified| {
+            let dir = fs::File::open(temp_dir.path()).unwrap();
+            dir.set_modified(modified).unwrap();
+        };
+        let modified = fs::metadata(temp_dir.path())
+            .and_then(|m| m.modified())
+            .unwrap();
+
+        // Not looked for, as if the directory was unchanged
+        fs::write(
+            temp_dir.path().join("photo.xmp"),
+            xmp::tests::SIDECAR_ATTRIBUTES,
+        )
+        .unwrap();
+        set_dir_modified(modified);
+        assert_eq!(source(), Some(MetadataSource::Embedded));
+
+        set_dir_modified(modified + std::time::Duration::from_secs(1));
+        assert_eq!(source(), Some(MetadataSource::Xmp));
+    }
+
+    #[test]
+    fn read_photo_again_when_sidecar_changed() {
+        let temp_dir = tempdir().unwrap();
+        let photo = temp_dir.path().join("photo.tif");
+        fs::write(&photo, make_exif_tiff((37.3317, -122.0307), false)).unwrap();
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let searcher = Searcher::new(10000.0, (37.33, -122.03), 10, true, true, exif_cache);
+        let source = || searcher.read_photo(&photo).unwrap().source;
+        assert_eq!(source(), Some(MetadataSource::Embedded));
+
+        // Added
+        let sidecar = temp_dir.path().join("photo.xmp");
+        fs::write(&sidecar, xmp::tests::SIDECAR_ATTRIBUTES).unwrap();
+        assert_eq!(source(), Some(MetadataSource::Xmp));
+
+        // Edited, here to a sidecar without coordinates
+        fs::write(&sidecar, "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>").unwrap();
+        assert_eq!(source(), Some(MetadataSource::Embedded));
+
+        // Removed
+        fs::write(&sidecar, xmp::tests::SIDECAR_ATTRIBUTES).unwrap();
+        assert_eq!(source(), Some(MetadataSource::Xmp));
+        fs::remove_file(&sidecar).unwrap();
+        assert_eq!(source(), Some(MetadataSource::Embedded));
+    }
+
+    #[test]
+    fn read_untimed_record_again() {
+        let temp_dir = tempdir().unwrap();
+        let photo = temp_dir.path().join("photo.tif");
+        fs::write(&photo, make_exif_tiff((37.3317, -122.0307), false)).unwrap();
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let time_window = TimeWindow {
+            after: Some("2024-03-01".parse().unwrap()),
+            before: None,
+        };
+        let searcher = Searcher::new(10000.0, (37.33, -122.03), 10, true, true, exif_cache)
+            .cache_check(CacheCheck::Trust)
+            .time_window(time_window);
+
+        // Written before capture times were read
+        let record = serde_json::json!({ "coordinates": [37.3317, -122.0307] });
+        searcher
+            .cache
+            .write(&searcher.path_to_key(&photo), record)
+            .unwrap();
+        let result = searcher.filter_by_path_str(photo.to_str().unwrap());
+
+        assert!(result.unwrap().taken_at.is_some());
+    }
+
+    #[test]
+    fn search_stops_early() {
+        let temp_dir = tempdir().unwrap();
+        let mut paths = Vec::new();
+        // Matches at 0, 2, 3 and 5
+        for (i, lon) in [0.0, 1.0, 0.0, 0.0, 1.0, 0.0].iter().enumerate() {
+            let path = temp_dir.path().join(format!("{}.tif", i));
+            fs::write(&path, make_exif_tiff((0.0, *lon), false)).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        let exif_cache = Cache::new(&temp_dir.path().join("cache")).unwrap();
+        let searcher = Searcher::new(1000.0, (0.0, 0.0), 2, true, true, exif_cache);
+        let scanned = Cell::new(0);
+
+        let found = searcher.search(paths.iter().inspect(|_| scanned.set(scanned.get() + 1)));
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].path.to_str().unwrap(), paths[2]);
+        assert_eq!(scanned.get(), 3);
+
+        let searcher = searcher.nearest(Some(1));
+        scanned.set(0);
+        let found = searcher.search(paths.iter().inspect(|_| scanned.set(scanned.get() + 1)));
+        assert_eq!(found.len(), 1);
+        assert_eq!(scanned.get(), 3);
+    }
+
+    #[test]
+    fn search_in_parallel() {
+        let temp_dir = tempdir().unwrap();
+        let mut paths = Vec::new();
+        for i in 0..100 {
+            let path = temp_dir.path().join(format!("{:03}.tif", i));
+            let lon = (i % 7) as f64 * 0.001;
+            fs::write(&path, make_exif_tiff((0.0, lon), i % 2 == 0)).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        // Duplicates race on the same cache entry
+        paths.extend(paths.clone());
+        let sequential = Searcher::new(
+            300.0,
+            (0.0, 0.0),
+            -1,
+            false,
+            false,
+            Cache::new(&temp_dir.path().join("sequential")).unwrap(),
+        );
+        let parallel = Searcher::new(
+            300.0,
+            (0.0, 0.0),
+            -1,
+            false,
+            false,
+            Cache::new(&temp_dir.path().join("parallel")).unwrap(),
+        )
+        .jobs(4);
+
+        let expected: Vec<_> = sequential
+            .search(&paths)
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        let found: Vec<_> = parallel
+            .search(&paths)
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+
+        assert_eq!(expected.len(), 2 * 44);
+        assert_eq!(found, expected);
+
+        let parallel = parallel.nearest(Some(5));
+        let found: Vec<_> = parallel
+            .search(&paths)
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        let nearest = sequential.nearest(Some(5)).search(&paths);
+        assert_eq!(
+            found,
+            nearest.into_iter().map(|f| f.path).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn visit_raw_files() {
+        let temp_dir = tempdir().unwrap();
+        let photo_dir = temp_dir.path().join("raw");
+        fs::create_dir_all(&photo_dir).unwrap();
+        for filename in ["a.dng", "b.cr3", "c.nef", "d.raf", "e.xmp"] {
+            fs::write(photo_dir.join(filename), b"").unwrap();
+        }
+
+        let mut found: Vec<_> = visit_paths(temp_dir.path().to_str().unwrap())
+            .unwrap()
+            .map(|path| {
+                let path = path.unwrap();
+                Path::new(&path)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        found.sort();
+
+        assert_eq!(found, ["a.dng", "b.cr3", "c.nef", "d.raf"]);
+    }
+
+    fn make_tiff_without_gps() -> Vec<u8> {
+        let description = exif::Field {
+            tag: exif::Tag::ImageDescription,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![b"No GPS".to_vec()]),
+        };
+        let mut writer = exif::experimental::Writer::new();
+        writer.push_field(&description);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        tiff.into_inner()
+    }
+
+    pub(crate) fn make_exif_tiff((lat, lon): (f64, f64), little_endian: bool) -> Vec<u8> {
+        fn to_dms(value: f64) -> exif::Value {
+            let value = value.abs();
+            let deg = value.trunc();
+            let min = ((value - deg) * 60.0).trunc();
+            let sec = (value - deg - min / 60.0) * 3600.0;
+            exif::Value::Rational(vec![
+                exif::Rational::from((deg as u32, 1)),
+                exif::Rational::from((min as u32, 1)),
+                exif::Rational::from(((sec * 10000.0).round() as u32, 10000)),
+            ])
+        }
+        let lat_ref = if lat < 0.0 { b"S" } else { b"N" };
+        let lon_ref = if lon < 0.0 { b"W" } else { b"E" };
+        let fields = [
+            (
+                exif::Tag::GPSLatitudeRef,
+                exif::Value::Ascii(vec![lat_ref.to_vec()]),
+            ),
+            (exif::Tag::GPSLatitude, to_dms(lat)),
+            (
+                exif::Tag::GPSLongitudeRef,
+                exif::Value::Ascii(vec![lon_ref.to_vec()]),
+            ),
+            (exif::Tag::GPSLongitude, to_dms(lon)),
+            (
+                exif::Tag::GPSDateStamp,
+                exif::Value::Ascii(vec![b"2024:03:15".to_vec()]),
+            ),
+            (
+                exif::Tag::GPSTimeStamp,
+                exif::Value::Rational(vec![(9, 1).into(), (20, 1).into(), (28, 1).into()]),
+            ),
+            (
+                exif::Tag::DateTimeOriginal,
+                exif::Value::Ascii(vec![b"2024:03:15 10:20:30".to_vec()]),
+            ),
+        ]
+        .map(|(tag, value)| exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value,
+        });
+
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields.iter() {
+            writer.push_field(field);
+        }
+        let mut buf = std::io::Cursor::new(Vec::new());
+        writer.write(&mut buf, little_endian).unwrap();
+        buf.into_inner()
+    }
+
     fn test_filter_path_with_photo(filename: &str) {
         let radius = 10000.0;
         let target_loc = (10.0, 10.0);
@@ -318,7 +1816,7 @@ mod tests {
         let temp_path = temp_dir.path();
         let exif_cache_dir = temp_path.join("exif");
         fs::create_dir_all(&exif_cache_dir).expect("Error creating exif cache dir");
-        let exif_cache = Cache::new(&exif_cache_dir);
+        let exif_cache = Cache::new(&exif_cache_dir).unwrap();
 
         let searcher = Searcher::new(
             radius,
@@ -327,7 +1825,8 @@ mod tests {
             sort_by_distance,
             verbose,
             exif_cache,
-        );
+        )
+        .cache_check(CacheCheck::Trust);
 
         // Read from photo
         let photo_dir = temp_path.join("photos");
@@ -0,0 +1,430 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use url::Url;
+
+/// Point on Earth parsed from whatever people paste: `lat,lon`, DMS, `geo:`
+/// URIs, Google/OSM/Apple Maps links, geohashes, plus codes, Maidenhead
+/// locators, UTM or MGRS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Location {
+    pub fn coords(&self) -> (f64, f64) {
+        (self.lat, self.lon)
+    }
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let lower = text.to_ascii_lowercase();
+        let coords = if lower.starts_with("geo:") {
+            parse_geo_uri(&text[4..])
+        } else if lower.starts_with("http://") || lower.starts_with("https://") {
+            parse_map_url(text)
+        } else {
+            latlon::parse(text)
+                .ok()
+                .map(|point| (point.y(), point.x()))
+                .or_else(|| parse_utm(text))
+                .or_else(|| parse_mgrs(text))
+                .or_else(|| parse_plus_code(text))
+                .or_else(|| parse_maidenhead(text))
+                .or_else(|| parse_geohash(text))
+        };
+
+        match coords {
+            Some((lat, lon))
+                if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) =>
+            {
+                Ok(Location { lat, lon })
+            }
+            _ => Err(format!("Unrecognized location: {}", s)),
+        }
+    }
+}
+
+// Decimal `lat,lon` as used in URIs and URLs
+fn parse_pair(text: &str) -> Option<(f64, f64)> {
+    let mut values = text.split(',').map(|value| value.trim().parse::<f64>());
+    match (values.next(), values.next()) {
+        (Some(Ok(lat)), Some(Ok(lon))) => Some((lat, lon)),
+        _ => None,
+    }
+}
+
+// RFC 5870 `geo:lat,lon[,alt][;crs=wgs84][;u=10]`
+fn parse_geo_uri(text: &str) -> Option<(f64, f64)> {
+    let coords = text.split([';', '?']).next()?;
+    parse_pair(coords)
+}
+
+// Google Maps: `!3dLAT!4dLON` for the place, `@lat,lon,zoom` for the view or
+// `?q=lat,lon`; OSM: `?mlat=..&mlon=..` or `#map=zoom/lat/lon`; Apple Maps:
+// `?ll=lat,lon` or `?q=lat,lon`
+fn parse_map_url(text: &str) -> Option<(f64, f64)> {
+    let url = Url::parse(text).ok()?;
+
+    let marked = |marker: &str| {
+        let start = text.find(marker)? + marker.len();
+        let value = &text[start..];
+        let end = value
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+            .unwrap_or(value.len());
+        value[..end].parse::<f64>().ok()
+    };
+    if let (Some(lat), Some(lon)) = (marked("!3d"), marked("!4d")) {
+        return Some((lat, lon));
+    }
+
+    let query = |key: &str| {
+        url.query_pairs()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.into_owned())
+    };
+    if let (Some(lat), Some(lon)) = (query("mlat"), query("mlon")) {
+        return Some((lat.parse().ok()?, lon.parse().ok()?));
+    }
+    for key in ["ll", "q", "query", "center", "sll", "daddr", "destination"] {
+        if let Some(coords) = query(key).and_then(|value| parse_pair(&value)) {
+            return Some(coords);
+        }
+    }
+
+    if let Some(map) = url.fragment().and_then(|fragment| {
+        fragment
+            .split('&')
+            .find_map(|part| part.strip_prefix("map="))
+    }) {
+        let mut values = map.split('/').skip(1).map(|value| value.parse::<f64>());
+        if let (Some(Ok(lat)), Some(Ok(lon))) = (values.next(), values.next()) {
+            return Some((lat, lon));
+        }
+    }
+
+    url.path_segments()?
+        .find_map(|segment| parse_pair(segment.strip_prefix('@')?))
+}
+
+fn parse_geohash(text: &str) -> Option<(f64, f64)> {
+    const ALPHABET: &str = "0123456789bcdefghjkmnpqrstuvwxyz";
+    if text.is_empty() || text.len() > 12 || !text.chars().all(|c| ALPHABET.contains(c)) {
+        return None;
+    }
+    let (center, _, _) = geohash::decode(text).ok()?;
+    Some((center.y, center.x))
+}
+
+// Full Open Location Code such as `8FVC9G8F+6X`, optionally padded with `0`
+fn parse_plus_code(text: &str) -> Option<(f64, f64)> {
+    const ALPHABET: &[u8] = b"23456789CFGHJMPQRVWX";
+    let code = text.to_ascii_uppercase();
+    if code.find('+') != Some(8) {
+        return None;
+    }
+    let digits: Vec<usize> = code
+        .bytes()
+        .filter(|c| *c != b'+' && *c != b'0')
+        .map(|c| ALPHABET.iter().position(|a| *a == c))
+        .collect::<Option<_>>()?;
+    if digits.len() < 2 || digits.len() % 2 == 1 && digits.len() < 10 {
+        return None;
+    }
+
+    let (mut lat, mut lon) = (-90.0, -180.0);
+    let mut lat_step = 20.0;
+    let mut lon_step = 20.0;
+    for (i, digit) in digits.iter().enumerate() {
+        if i < 10 {
+            // Pairs of latitude and longitude digits in base 20
+            if i % 2 == 0 {
+                if i > 0 {
+                    lat_step /= 20.0;
+                    lon_step /= 20.0;
+                }
+                lat += lat_step * *digit as f64;
+            } else {
+                lon += lon_step * *digit as f64;
+            }
+        } else {
+            // Grid refinement of 5 rows by 4 columns
+            lat_step /= 5.0;
+            lon_step /= 4.0;
+            lat += lat_step * (digit / 4) as f64;
+            lon += lon_step * (digit % 4) as f64;
+        }
+    }
+    Some((lat + lat_step / 2.0, lon + lon_step / 2.0))
+}
+
+// Maidenhead locator such as `FN20` or `FN20xr`, with upper case fields so it
+// is not confused with a geohash
+fn parse_maidenhead(text: &str) -> Option<(f64, f64)> {
+    let chars: Vec<char> = text.chars().collect();
+    if ![4, 6, 8].contains(&chars.len()) || !('A'..='R').contains(&chars[0]) {
+        return None;
+    }
+    // Longitude and latitude size of each pair of characters in degrees,
+    // with the first character of the pair
+    let pairs = [
+        ('A', 18, 20.0, 10.0),
+        ('0', 10, 2.0, 1.0),
+        ('A', 24, 5.0 / 60.0, 2.5 / 60.0),
+        ('0', 10, 0.5 / 60.0, 0.25 / 60.0),
+    ];
+    let (mut lat, mut lon) = (-90.0, -180.0);
+    let (mut lat_step, mut lon_step) = (0.0, 0.0);
+    for (pair, (first, count, lon_size, lat_size)) in chars.chunks(2).zip(pairs) {
+        let index = |c: char| {
+            let index = c.to_ascii_uppercase() as i32 - first as i32;
+            (0..count).contains(&index).then_some(index as f64)
+        };
+        lon += index(pair[0])? * lon_size;
+        lat += index(pair[1])? * lat_size;
+        (lat_step, lon_step) = (lat_size, lon_size);
+    }
+    Some((lat + lat_step / 2.0, lon + lon_step / 2.0))
+}
+
+// WGS 84 ellipsoid
+const A: f64 = 6378137.0;
+const F: f64 = 1.0 / 298.257223563;
+const K0: f64 = 0.9996;
+const BANDS: &str = "CDEFGHJKLMNPQRSTUVWX";
+
+// UTM such as `17T 630084 4833438`, where bands N and above are northern
+fn parse_utm(text: &str) -> Option<(f64, f64)> {
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    let (zone, easting, northing) = match parts[..] {
+        [zone, easting, northing] => (zone, easting, northing),
+        _ => return None,
+    };
+    let (zone, band) = parse_zone(zone)?;
+    let easting = easting.trim_end_matches(['m', 'E']).parse::<f64>().ok()?;
+    let northing = northing.trim_end_matches(['m', 'N']).parse::<f64>().ok()?;
+    Some(utm_to_lat_lon(zone, band >= 'N', easting, northing))
+}
+
+// `17T` into zone 17 and band T
+fn parse_zone(text: &str) -> Option<(u32, char)> {
+    let (split, band) = text.char_indices().last()?;
+    let band = band.to_ascii_uppercase();
+    let zone: u32 = text[..split].parse().ok()?;
+    if !(1..=60).contains(&zone) || !BANDS.contains(band) {
+        return None;
+    }
+    Some((zone, band))
+}
+
+// MGRS such as `17TNE3008433438` or `17T NE 30084 33438`
+fn parse_mgrs(text: &str) -> Option<(f64, f64)> {
+    let text: String = text.split_whitespace().collect();
+    let text = text.to_ascii_uppercase();
+    let zone_len = text.find(|c: char| c.is_ascii_alphabetic())? + 1;
+    let (zone, band) = parse_zone(text.get(..zone_len)?)?;
+    let square: Vec<char> = text.get(zone_len..zone_len + 2)?.chars().collect();
+    let digits = text.get(zone_len + 2..)?;
+    if digits.len() % 2 == 1 || digits.len() > 10 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let precision = digits.len() / 2;
+    // Digits are truncated, so take the middle of the cell
+    let scale = 10f64.powi(5 - precision as i32);
+    let parse = |digits: &str| -> Option<f64> {
+        let value = if digits.is_empty() {
+            0.0
+        } else {
+            digits.parse().ok()?
+        };
+        Some(value * scale + scale / 2.0)
+    };
+    let easting_in_square = parse(&digits[..precision])?;
+    let northing_in_square = parse(&digits[precision..])?;
+
+    // Column letters cycle through three sets by zone, row letters repeat
+    // every 2000 km and start at F in even zones
+    let columns = ["ABCDEFGH", "JKLMNPQR", "STUVWXYZ"][(zone as usize - 1) % 3];
+    let rows = "ABCDEFGHJKLMNPQRSTUV";
+    let column = columns.find(square[0])?;
+    let row = rows.find(square[1])?;
+    let row = (row + 20 - if zone % 2 == 0 { 5 } else { 0 }) % 20;
+    let easting = (column + 1) as f64 * 100_000.0 + easting_in_square;
+    let mut northing = row as f64 * 100_000.0 + northing_in_square;
+
+    // Add cycles of 2000 km until the northing reaches the latitude band,
+    // with some slack as the band edge is lower away from the central
+    // meridian
+    let band_index = BANDS.find(band)? as f64;
+    let band_south = band_index * 8.0 - 80.0;
+    let northern = band >= 'N';
+    let band_northing =
+        K0 * meridian_arc(band_south.to_radians()) + if northern { 0.0 } else { 10_000_000.0 };
+    let min_northing = band_northing - 100_000.0;
+    while northing < min_northing {
+        northing += 2_000_000.0;
+    }
+    Some(utm_to_lat_lon(zone, northern, easting, northing))
+}
+
+// Distance along the central meridian from the equator to latitude
+fn meridian_arc(lat: f64) -> f64 {
+    let e2 = F * (2.0 - F);
+    let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+    A * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * lat
+        - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * lat).sin()
+        + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * lat).sin()
+        - (35.0 * e6 / 3072.0) * (6.0 * lat).sin())
+}
+
+// Inverse transverse Mercator as given by Snyder, accurate to well below a
+// meter within a zone
+fn utm_to_lat_lon(zone: u32, northern: bool, easting: f64, northing: f64) -> (f64, f64) {
+    let e2 = F * (2.0 - F);
+    let ep2 = e2 / (1.0 - e2);
+    let e1 = (1.0 - (1.0 - e2).sqrt()) / (1.0 + (1.0 - e2).sqrt());
+    let x = easting - 500_000.0;
+    let y = if northern {
+        northing
+    } else {
+        northing - 10_000_000.0
+    };
+
+    let m = y / K0;
+    let mu = m / (A * (1.0 - e2 / 4.0 - 3.0 * e2 * e2 / 64.0 - 5.0 * e2.powi(3) / 256.0));
+    let phi1 = mu
+        + (3.0 * e1 / 2.0 - 27.0 * e1.powi(3) / 32.0) * (2.0 * mu).sin()
+        + (21.0 * e1 * e1 / 16.0 - 55.0 * e1.powi(4) / 32.0) * (4.0 * mu).sin()
+        + (151.0 * e1.powi(3) / 96.0) * (6.0 * mu).sin()
+        + (1097.0 * e1.powi(4) / 512.0) * (8.0 * mu).sin();
+
+    let (sin1, cos1, tan1) = (phi1.sin(), phi1.cos(), phi1.tan());
+    let n1 = A / (1.0 - e2 * sin1 * sin1).sqrt();
+    let t1 = tan1 * tan1;
+    let c1 = ep2 * cos1 * cos1;
+    let r1 = A * (1.0 - e2) / (1.0 - e2 * sin1 * sin1).powf(1.5);
+    let d = x / (n1 * K0);
+
+    let lat = phi1
+        - (n1 * tan1 / r1)
+            * (d * d / 2.0
+                - (5.0 + 3.0 * t1 + 10.0 * c1 - 4.0 * c1 * c1 - 9.0 * ep2) * d.powi(4) / 24.0
+                + (61.0 + 90.0 * t1 + 298.0 * c1 + 45.0 * t1 * t1 - 252.0 * ep2 - 3.0 * c1 * c1)
+                    * d.powi(6)
+                    / 720.0);
+    let lon = (d - (1.0 + 2.0 * t1 + c1) * d.powi(3) / 6.0
+        + (5.0 - 2.0 * c1 + 28.0 * t1 - 3.0 * c1 * c1 + 8.0 * ep2 + 24.0 * t1 * t1) * d.powi(5)
+            / 120.0)
+        / cos1;
+    let central_meridian = (zone as f64 * 6.0 - 183.0) * PI / 180.0;
+
+    (lat.to_degrees(), (central_meridian + lon).to_degrees())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_location(text: &str, expected: (f64, f64), tolerance: f64) {
+        let location: Location = text.parse().unwrap();
+        assert!(
+            (location.lat - expected.0).abs() < tolerance
+                && (location.lon - expected.1).abs() < tolerance,
+            "{}: {:?}",
+            text,
+            location
+        );
+    }
+
+    #[test]
+    fn parse_decimal_and_dms() {
+        assert_location("40.4461,-79.9822", (40.4461, -79.9822), 1e-9);
+        assert_location("40.4461, -79.9822", (40.4461, -79.9822), 1e-9);
+        assert_location("40°26'46\"N 79°58'56\"W", (40.4461, -79.9822), 1e-3);
+    }
+
+    #[test]
+    fn parse_uris_and_urls() {
+        assert_location("geo:48.2010,16.3695;u=40", (48.2010, 16.3695), 1e-9);
+        assert_location("GEO:48.2010,16.3695,183", (48.2010, 16.3695), 1e-9);
+        assert_location(
+            "https://www.google.com/maps/place/Eiffel+Tower/@48.8583701,2.2919064,17z/data=!3m1!4b1!4m6!3m5!1s0x0:0x0!8m2!3d48.8583701!4d2.2944813",
+            (48.8583701, 2.2944813),
+            1e-9,
+        );
+        assert_location(
+            "https://www.google.com/maps/@48.8583701,2.2919064,17z",
+            (48.8583701, 2.2919064),
+            1e-9,
+        );
+        assert_location(
+            "https://maps.google.com/?q=48.8584,2.2945",
+            (48.8584, 2.2945),
+            1e-9,
+        );
+        assert_location(
+            "https://www.openstreetmap.org/?mlat=48.8584&mlon=2.2945#map=17/48.85/2.29",
+            (48.8584, 2.2945),
+            1e-9,
+        );
+        assert_location(
+            "https://www.openstreetmap.org/#map=17/48.85840/2.29450",
+            (48.8584, 2.2945),
+            1e-9,
+        );
+        assert_location(
+            "https://maps.apple.com/?ll=48.8584,2.2945&q=Eiffel%20Tower",
+            (48.8584, 2.2945),
+            1e-9,
+        );
+        assert!("https://example.com/".parse::<Location>().is_err());
+    }
+
+    #[test]
+    fn parse_grid_codes() {
+        // Geohash
+        assert_location("u09tunqu", (48.8584, 2.2945), 1e-3);
+        // Plus code
+        assert_location("8FW4V75V+8Q", (48.8584, 2.2945), 1e-3);
+        assert_location("8fw4v75v+8q", (48.8584, 2.2945), 1e-3);
+        assert_location("8FW4V700+", (48.875, 2.275), 1e-9);
+        // Maidenhead
+        assert_location("JN18du", (48.854, 2.291), 1e-2);
+        assert_location("FN20", (40.5, -75.0), 1e-9);
+
+        // Examples in README
+        for text in [
+            "dppn5f",
+            "87G2C2W9+C4",
+            "FN00ak",
+            "17T 586312 4477769",
+            "17TNE8631177769",
+        ] {
+            assert_location(text, (40.4461, -79.9822), 5e-2);
+        }
+    }
+
+    #[test]
+    fn parse_utm_and_mgrs() {
+        // Eiffel Tower
+        assert_location("31U 448252 5411955", (48.8584, 2.2945), 1e-5);
+        assert_location("31UDQ4825211954", (48.8584, 2.2945), 1e-5);
+        assert_location("31U DQ 48252 11954", (48.8584, 2.2945), 1e-5);
+        assert_location("31UDQ4811", (48.8584, 2.2945), 1e-2);
+        // Sydney Opera House
+        assert_location("56H 334901 6252289", (-33.8568, 151.2153), 1e-5);
+        assert_location("56HLH3490052288", (-33.8568, 151.2153), 1e-5);
+        // Statue of Liberty, even zone
+        assert_location("18TWL8073504695", (40.6892, -74.0445), 1e-5);
+
+        assert!("31I 448252 5411935".parse::<Location>().is_err());
+        assert!("not a place".parse::<Location>().is_err());
+        assert!("1é 2 3".parse::<Location>().is_err());
+        assert!("31É 448252 5411935".parse::<Location>().is_err());
+        assert!("3é1UDQ4811".parse::<Location>().is_err());
+    }
+}
@@ -1,99 +1,456 @@
 use atty::Stream;
-use clap::Parser;
+use chrono::Duration;
+use clap::{Args, Parser, Subcommand};
 use directories::ProjectDirs;
-use std::{fs, io};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 
-use imnear::Cache;
+use imnear::{
+    Area, BoundingBox, Cache, CacheCheck, CacheLimits, CaptureTime, Length, Location, Precedence,
+    Target, TimeWindow, Walker,
+};
 mod geocode;
 
 fn main() -> Result<(), String> {
     let args = Cli::parse();
 
+    // Without a radius the nearest photos are searched anywhere
+    let radius = match (args.radius, args.nearest) {
+        (Some(radius), _) => Some(radius.meters()),
+        (None, Some(_)) => Some(f64::INFINITY),
+        (None, None) => None,
+    };
+
     // Get geocode cache
     let cache_dir = ProjectDirs::from("", "", "imnear")
-        .expect("Cannot find app cache dir")
+        .ok_or("Cannot find app cache dir")?
         .cache_dir()
         .to_path_buf();
-    let geocode_cache_dir = cache_dir.join("nominatim");
-    fs::create_dir_all(&geocode_cache_dir).expect("Error creating geocode cache dir");
-    let geocode_cache = Cache::new(&geocode_cache_dir);
+    // Applied after searching, while `cache prune` has its own
+    let limits = CacheLimits {
+        max_entries: args.cache_max_entries,
+        max_bytes: args.cache_max_size,
+        max_age: args.cache_max_age,
+    };
+    let geocode_cache = open_cache(&cache_dir, "nominatim").limits(limits.clone());
 
     // Get exif cache
-    let exif_cache_dir = cache_dir.join("exif");
-    fs::create_dir_all(&exif_cache_dir).expect("Error creating exif cache dir");
-    let exif_cache = Cache::new(&exif_cache_dir);
-
-    // Use address if provided
-    let coords = match args.address {
-        Some(addr) => match geocode::locate(&addr, geocode_cache) {
-            Some(coords) => Some(coords),
-            _ => return Err(format!("Found no location info for {}", &addr)),
-        },
-        None => None,
-    };
-    let (lat, lon) = match coords {
-        Some((lat, lon)) => {
+    let exif_cache = open_cache(&cache_dir, "exif").limits(limits);
+
+    if let Some(Command::Cache {
+        command: CacheCommand::Prune(prune),
+    }) = &args.command
+    {
+        return prune_caches(
+            prune,
+            &[("exif", &exif_cache), ("nominatim", &geocode_cache)],
+        );
+    }
+
+    // Only areas other than boxes, polygons and boundaries need a radius
+    let require_radius =
+        || radius.ok_or_else(|| "Radius is required, e.g. 500m or 5km".to_string());
+    let area = if let Some(bbox) = args.bbox {
+        Area::BoundingBox(bbox)
+    } else if let Some(path) = args.within {
+        Area::Polygon(imnear::polygon::read_polygons(&path).map_err(|e| e.to_string())?)
+    } else if let Some(path) = args.along {
+        let route = imnear::route::read_route(&path).map_err(|e| e.to_string())?;
+        if args.verbose {
+            eprintln!("Found route of {} m", route.length())
+        }
+        Area::Corridor {
+            route,
+            radius: require_radius()?,
+        }
+    } else if args.near_photo.is_some() {
+        // Centered on the photo once the searcher has read it below
+        Area::Circle {
+            center: (0.0, 0.0),
+            radius: require_radius()?,
+        }
+    } else if args.inside {
+        let addr = match &args.address[..] {
+            [addr] => addr,
+            _ => return Err("Expected one address to search inside".to_string()),
+        };
+        match geocode::locate_area(addr, &geocode_cache).map_err(|e| e.to_string())? {
+            Some(area) => {
+                if args.verbose {
+                    eprintln!("Found area centered at: {:?}", area.center())
+                }
+                area
+            }
+            None => return Err(format!("Found no area info for {}", addr)),
+        }
+    } else {
+        let mut targets = match args.targets {
+            Some(path) => imnear::target::read_targets(&path).map_err(|e| e.to_string())?,
+            None => Vec::new(),
+        };
+        // Use addresses if provided
+        for addr in args.address {
+            let (lat, lon) =
+                match geocode::locate(&addr, &geocode_cache).map_err(|e| e.to_string())? {
+                    Some(coords) => coords,
+                    _ => return Err(format!("Found no location info for {}", &addr)),
+                };
             if args.verbose {
                 eprintln!("Found coordinates: {}, {}", lat, lon)
             }
-            (lat, lon)
+            targets.push(Target {
+                name: addr,
+                coords: (lat, lon),
+            });
+        }
+        if args.lat.len() != args.lon.len() {
+            return Err("Expected as many --lat as --lon".to_string());
+        }
+        for (lat, lon) in args.lat.into_iter().zip(args.lon) {
+            targets.push(Target::at((lat, lon)));
+        }
+        for location in args.at {
+            targets.push(Target::at(location.coords()));
+        }
+
+        let radius = require_radius()?;
+        match targets.len() {
+            0 => return Err("Target location is missing".to_string()),
+            1 => Area::Circle {
+                center: targets[0].coords,
+                radius,
+            },
+            _ => Area::Targets { targets, radius },
         }
-        None => (
-            args.lat.expect("Latitude is missing"),
-            args.lon.expect("Longitude is missing"),
-        ),
     };
 
     // Search
-    let searcher = imnear::Searcher::new(
-        args.radius,
-        (lat, lon),
+    let cache_check = if args.trust_cache {
+        CacheCheck::Trust
+    } else if args.check_hash {
+        CacheCheck::Hash
+    } else {
+        CacheCheck::Stamp
+    };
+    let searcher = imnear::Searcher::with_area(
+        area,
         args.early_stop_count,
         args.sort_by_distance,
         args.verbose,
         exif_cache,
-    );
-    let found: Vec<_> = if is_stdin_piped() {
-        io::stdin()
-            .lines()
-            .filter_map(|line| searcher.filter_by_path_str(&line.unwrap()))
-            .collect()
+    )
+    .sort_by_route(args.sort_by_route)
+    .nearest(args.nearest)
+    .jobs(args.jobs)
+    .cache_check(cache_check)
+    .min_distance(args.min_radius.meters())
+    .sidecar_precedence(args.prefer)
+    .time_window(TimeWindow {
+        after: args.after,
+        before: args.before,
+    });
+    let searcher = match &args.near_photo {
+        Some(photo) => {
+            let metadata = searcher.read_photo(photo).map_err(|e| e.to_string())?;
+            let center = match metadata.coordinates {
+                Some(coords) => coords,
+                None => {
+                    return Err(format!(
+                        "Found no location info for {}",
+                        photo.to_string_lossy()
+                    ))
+                }
+            };
+            if args.verbose {
+                eprintln!("Found coordinates: {}, {}", center.0, center.1)
+            }
+            let mut searcher = searcher.area(Area::Circle {
+                center,
+                radius: require_radius()?,
+            });
+            if let Some(margin) = args.time_margin {
+                let taken_at = match metadata.taken_at {
+                    Some(taken_at) => taken_at,
+                    None => {
+                        return Err(format!(
+                            "Found no capture time for {}",
+                            photo.to_string_lossy()
+                        ))
+                    }
+                };
+                searcher = searcher.time_window(TimeWindow::around(taken_at, margin));
+            }
+            if args.exclude_photo {
+                searcher = searcher.exclude(photo);
+            }
+            searcher
+        }
+        None => searcher,
+    };
+    let found = if is_stdin_piped() {
+        searcher.search(io::stdin().lines().filter_map(|line| match line {
+            Ok(line) => Some(line),
+            Err(e) => {
+                eprintln!("Warning: Cannot read path from stdin: {}", e);
+                None
+            }
+        }))
     } else {
-        imnear::visit_paths(&args.dir)
-            .filter_map(|path| searcher.filter_by_path_str(&path))
-            .collect()
+        let walker = Walker::new(&args.dir)
+            .include(&args.include)
+            .exclude(&args.exclude)
+            .max_depth(args.max_depth)
+            .follow_symlinks(args.follow_symlinks)
+            .hidden(args.hidden)
+            .ignore_files(!args.no_ignore);
+        let walker = match &args.ext {
+            Some(extensions) => walker.extensions(extensions),
+            None => walker,
+        };
+        let paths = walker.paths().map_err(|e| e.to_string())?;
+        searcher.search(paths.filter_map(|path| match path {
+            Ok(path) => Some(path),
+            Err(e) => {
+                eprintln!("Warning: {}", e);
+                None
+            }
+        }))
     };
 
     searcher.print_result(found);
+    if let Err(e) = geocode_cache.flush().and_then(|_| geocode_cache.evict()) {
+        eprintln!("Warning: {}", e);
+    }
 
     Ok(())
 }
 
+/// Cache in a single file, into which the directory cache of earlier
+/// versions is copied on first use. Searches go on without a persistent
+/// cache if it cannot be opened.
+fn open_cache(cache_dir: &Path, name: &str) -> Cache {
+    let path = cache_dir.join(format!("{}.log", name));
+    let cache = match Cache::single_file(&path) {
+        Ok(cache) => cache,
+        Err(e) => {
+            eprintln!("Warning: {}, running without the {} cache", e, name);
+            return Cache::in_memory();
+        }
+    };
+    let dir = cache_dir.join(name);
+    if dir.is_dir() {
+        let imported = match cache.import(&dir) {
+            Ok(imported) => imported,
+            // Left in place to be imported next time
+            Err(e) => {
+                eprintln!("Warning: {}", e);
+                return cache;
+            }
+        };
+        // Kept aside rather than removed, in case the import missed entries
+        let old = cache_dir.join(format!("{}.old", name));
+        eprintln!(
+            "Moved {} {} cache entries into {}, the old cache is now {}",
+            imported,
+            name,
+            path.display(),
+            old.display()
+        );
+        if let Err(e) = fs::rename(&dir, &old) {
+            eprintln!("Warning: Cannot move {}: {}", dir.display(), e);
+        }
+    }
+    cache
+}
+
+fn prune_caches(args: &PruneArgs, caches: &[(&str, &Cache)]) -> Result<(), String> {
+    let limits = CacheLimits {
+        max_entries: args.max_entries,
+        max_bytes: args.max_size,
+        max_age: args.max_age,
+    };
+    for (name, cache) in caches {
+        // Only exif entries are about files
+        let is_orphan: Option<&dyn Fn(&serde_json::Value) -> bool> =
+            if args.orphans && *name == "exif" {
+                Some(&imnear::PhotoMetadata::is_orphan)
+            } else {
+                None
+            };
+        let report = cache.prune(&limits, is_orphan).map_err(|e| e.to_string())?;
+        if args.verbose {
+            for pruned in report.removed.iter() {
+                println!("{:?}\t{}", pruned.reason, pruned.key);
+            }
+        }
+        println!(
+            "{}: removed {} entries ({} bytes), kept {} ({} bytes)",
+            name,
+            report.removed.len(),
+            report
+                .removed
+                .iter()
+                .map(|pruned| pruned.bytes)
+                .sum::<u64>(),
+            report.kept_entries,
+            report.kept_bytes
+        );
+    }
+    Ok(())
+}
+
 /// Search photos near a geographic location
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Latitude of the target location
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Latitude of the target location, repeatable
     #[arg(long, allow_negative_numbers = true)]
-    lat: Option<f64>,
-    /// Longitude of the target location
+    lat: Vec<f64>,
+    /// Longitude of the target location, repeatable
     #[arg(long, allow_negative_numbers = true)]
-    lon: Option<f64>,
-    /// Address or search words
+    lon: Vec<f64>,
+    /// Any of lat,lon, DMS, geo: URI, map URL, geohash, MGRS
+    #[arg(long, value_name = "LOCATION", allow_hyphen_values = true)]
+    at: Vec<Location>,
+    /// Address or search words, repeatable
+    #[arg(long)]
+    address: Vec<String>,
+    /// CSV of name,lat,lon or GeoJSON points to search around
+    #[arg(long)]
+    targets: Option<PathBuf>,
+    /// Directory/folder to search, repeatable
+    #[arg(short, long, default_value = ".")]
+    dir: Vec<PathBuf>,
+    /// Only files up to N levels below --dir, 1 for its own
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+    /// Follow symbolic links, skipping those that loop
     #[arg(long)]
-    address: Option<String>,
-    /// Directory/folder to search from
-    #[arg(short, long, default_value_t = String::from("."))]
-    dir: String,
-    #[arg(short, long, default_value_t = -1)]
+    follow_symlinks: bool,
+    /// Also search hidden files and directories
+    #[arg(long)]
+    hidden: bool,
+    /// Search what .imnearignore and .gitignore files list too
+    #[arg(long)]
+    no_ignore: bool,
+    /// Only files with these extensions, e.g. jpg,mov
+    #[arg(long, value_delimiter = ',')]
+    ext: Option<Vec<String>>,
+    /// Only paths under --dir matching this glob, repeatable
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Skip paths under --dir matching this glob, repeatable
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Stop scanning after N matches, -1 for all
+    ///
+    /// Matches are counted in scan order, before any sorting or --nearest
+    /// ranking, so those only consider the first N matches found.
+    #[arg(short, long, default_value_t = -1, allow_negative_numbers = true)]
     early_stop_count: isize,
     #[arg(short, long, action)]
     sort_by_distance: bool,
     #[arg(short, long, action)]
     verbose: bool,
-    /// Max distance from the target location
-    radius: f64,
+    /// Read this many files in parallel
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+    /// Use cached metadata even of changed or missing files
+    #[arg(long, conflicts_with = "check_hash")]
+    trust_cache: bool,
+    /// Also compare file contents against the cache
+    #[arg(long)]
+    check_hash: bool,
+    /// Keep each cache to N entries after searching
+    #[arg(long, value_name = "N")]
+    cache_max_entries: Option<usize>,
+    /// Keep each cache to this many bytes after searching
+    #[arg(long, value_name = "SIZE", value_parser = imnear::cache::parse_size)]
+    cache_max_size: Option<u64>,
+    /// Drop cache entries unused for this long, e.g. 90d
+    #[arg(long, value_name = "AGE", value_parser = imnear::cache::parse_age)]
+    cache_max_age: Option<std::time::Duration>,
+    /// Only photos taken at or after this time
+    #[arg(long)]
+    after: Option<CaptureTime>,
+    /// Only photos taken before this time
+    #[arg(long)]
+    before: Option<CaptureTime>,
+    /// Sidecar or embedded metadata first
+    #[arg(long, default_value = "sidecar")]
+    prefer: Precedence,
+    /// Search within min_lon,min_lat,max_lon,max_lat instead
+    #[arg(long, allow_hyphen_values = true, conflicts_with_all = ["lat", "lon", "at", "address", "targets", "radius"])]
+    bbox: Option<BoundingBox>,
+    /// Search within polygons from a GeoJSON, KML or WKT file
+    #[arg(long, conflicts_with_all = ["lat", "lon", "at", "address", "targets", "radius", "bbox"])]
+    within: Option<PathBuf>,
+    /// Search within the boundary of the address instead
+    #[arg(long, action, requires = "address", conflicts_with_all = ["lat", "lon", "at", "targets", "radius", "bbox", "within"])]
+    inside: bool,
+    /// Search along a GPX or GeoJSON route within radius
+    #[arg(long, conflicts_with_all = ["lat", "lon", "at", "address", "targets", "bbox", "within", "inside"])]
+    along: Option<PathBuf>,
+    /// Order by position along the route
+    #[arg(long, action, requires = "along")]
+    sort_by_route: bool,
+    /// Search around where this photo was taken
+    #[arg(long, conflicts_with_all = ["lat", "lon", "at", "address", "targets", "bbox", "within", "inside", "along"])]
+    near_photo: Option<PathBuf>,
+    /// Only photos taken within e.g. 2h of the reference photo
+    #[arg(long, value_parser = imnear::capture_time::parse_duration, requires = "near_photo", conflicts_with_all = ["after", "before"])]
+    time_margin: Option<Duration>,
+    /// Leave the reference photo out of the results
+    #[arg(long, action, requires = "near_photo")]
+    exclude_photo: bool,
+    /// Only the K closest photos, sorted by distance
+    #[arg(long, value_name = "K")]
+    nearest: Option<usize>,
+    /// Min distance from the target location
+    #[arg(long, default_value = "0")]
+    min_radius: Length,
+    /// Max distance such as 500m, 5km, 3mi, 2nmi or 1000ft; meters if bare
+    radius: Option<Length>,
 }
 
 fn is_stdin_piped() -> bool {
     !atty::is(Stream::Stdin)
 }
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage the metadata and geocoding caches
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove old and unused entries, most recently used kept first
+    Prune(PruneArgs),
+}
+
+#[derive(Args)]
+struct PruneArgs {
+    /// Remove entries of photos that no longer exist
+    #[arg(long)]
+    orphans: bool,
+    /// Remove entries unused for this long, e.g. 90d
+    #[arg(long, value_parser = imnear::cache::parse_age)]
+    max_age: Option<std::time::Duration>,
+    /// Keep at most N entries per cache
+    #[arg(long, value_name = "N")]
+    max_entries: Option<usize>,
+    /// Keep at most this many bytes per cache, e.g. 500MB
+    #[arg(long, value_parser = imnear::cache::parse_size)]
+    max_size: Option<u64>,
+    /// List each removed entry
+    #[arg(short, long)]
+    verbose: bool,
+}
@@ -1,12 +1,275 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::CaptureTime;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct PhotoMetadata {
     pub coordinates: Option<(f64, f64)>,
+    /// Where the coordinates were read from
+    #[serde(default)]
+    pub source: Option<MetadataSource>,
+    #[serde(default)]
+    pub taken_at: Option<CaptureTime>,
+    /// Coordinates of the first sidecar found next to the file, cached apart
+    /// from the embedded ones so that either can win in later searches
+    #[serde(default)]
+    pub sidecar: Option<((f64, f64), MetadataSource)>,
+    /// State of the file when it was read, to tell if a cached record is
+    /// still valid
+    #[serde(default)]
+    pub file: Option<FileStamp>,
+    /// Names and states of the sidecars next to the file when it was read
+    #[serde(default)]
+    pub sidecar_files: Vec<(PathBuf, FileStamp)>,
+    /// Modification time of the directory of the file when it was read.
+    /// Sidecars are only looked for again once it changed, as adding or
+    /// removing one changes it.
+    #[serde(default)]
+    pub dir_modified: Option<u64>,
+    /// Absolute path of the file, to tell when it no longer exists
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+impl PhotoMetadata {
+    /// Coordinates that win by precedence, with their source
+    pub fn with_precedence(self, precedence: Precedence) -> PhotoMetadata {
+        let use_sidecar = match precedence {
+            Precedence::Sidecar => true,
+            Precedence::Embedded => self.coordinates.is_none(),
+        };
+        match self.sidecar {
+            Some((coords, source)) if use_sidecar => PhotoMetadata {
+                coordinates: Some(coords),
+                source: Some(source),
+                ..self
+            },
+            _ => self,
+        }
+    }
+
+    /// Whether a cached record can be used as it is. Records written before
+    /// capture times were read lack `taken_at`, and those written before
+    /// sidecar coordinates were kept apart hold them as the embedded ones.
+    pub fn is_current(record: &serde_json::Value) -> bool {
+        let from_sidecar = matches!(record["source"].as_str(), Some("Xmp" | "Takeout"));
+        record.get("taken_at").is_some() && (!from_sidecar || record.get("sidecar").is_some())
+    }
+
+    /// Whether the file and its sidecars are as they were when this record
+    /// was read. Sidecars that were added or removed since also count.
+    pub fn is_stamped(&self, file: &FileStamp, sidecar_files: &[(PathBuf, FileStamp)]) -> bool {
+        self.file.as_ref().is_some_and(|stamp| stamp.matches(file))
+            && self.sidecar_files.len() == sidecar_files.len()
+            && self.sidecar_files.iter().zip(sidecar_files).all(
+                |((name, stamp), (other_name, other))| name == other_name && stamp.matches(other),
+            )
+    }
+
+    /// Whether a cached record is about a file that no longer exists.
+    /// Records written before paths were stored are kept.
+    pub fn is_orphan(record: &serde_json::Value) -> bool {
+        record["path"]
+            .as_str()
+            .is_some_and(|path| !Path::new(path).exists())
+    }
 }
 
-impl Default for PhotoMetadata {
-    fn default() -> Self {
-        Self { coordinates: None }
+/// Size, modification time and optionally a content hash of a file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// Hash of the start and end of the file, which is where metadata is
+    /// edited in place
+    #[serde(default)]
+    pub hash: Option<u64>,
+}
+
+const HASH_SAMPLE: u64 = 64 * 1024;
+
+impl FileStamp {
+    pub fn of(path: &Path, with_hash: bool) -> io::Result<FileStamp> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+        let modified = modified_nanos(&metadata);
+        let hash = if with_hash {
+            Some(sample_hash(&mut file, size)?)
+        } else {
+            None
+        };
+        Ok(FileStamp {
+            size,
+            modified,
+            hash,
+        })
+    }
+
+    /// Whether both stamps are of the same state of a file. Hashes are only
+    /// compared when both have one, so that stamps taken with and without
+    /// them can be mixed.
+    pub fn matches(&self, other: &FileStamp) -> bool {
+        self.size == other.size
+            && self.modified == other.modified
+            && match (self.hash, other.hash) {
+                (Some(hash), Some(other)) => hash == other,
+                _ => true,
+            }
+    }
+}
+
+/// Modification time of the directory holding a file, in nanoseconds since
+/// the Unix epoch
+pub fn dir_modified(path: &Path) -> Option<u64> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    modified_nanos(&fs::metadata(dir).ok()?)
+}
+
+fn modified_nanos(metadata: &fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .and_then(|since| u64::try_from(since.as_nanos()).ok())
+}
+
+// FNV-1a, which unlike the std hashers stays the same across releases
+fn sample_hash(file: &mut File, size: u64) -> io::Result<u64> {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut update = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    let mut buf = Vec::with_capacity(HASH_SAMPLE as usize);
+    file.by_ref().take(HASH_SAMPLE).read_to_end(&mut buf)?;
+    update(&buf);
+    if size > HASH_SAMPLE {
+        buf.clear();
+        file.seek(SeekFrom::Start((size - HASH_SAMPLE).max(HASH_SAMPLE)))?;
+        file.by_ref().take(HASH_SAMPLE).read_to_end(&mut buf)?;
+        update(&buf);
+    }
+    Ok(hash)
+}
+
+/// How a cached record is checked against its file before it is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheCheck {
+    /// Use records as they are, even for missing files such as those on an
+    /// unmounted drive
+    Trust,
+    /// Read the file again when its size or modification time changed
+    #[default]
+    Stamp,
+    /// Also read it again when its content hash changed
+    Hash,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataSource {
+    /// Exif or container metadata in the file itself
+    Embedded,
+    /// XMP sidecar file next to the photo
+    Xmp,
+    /// Google Takeout JSON sidecar file next to the photo
+    Takeout,
+}
+
+/// Which coordinates win when both sidecar and embedded metadata have them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precedence {
+    #[default]
+    Sidecar,
+    Embedded,
+}
+
+impl FromStr for Precedence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sidecar" => Ok(Precedence::Sidecar),
+            "embedded" => Ok(Precedence::Embedded),
+            _ => Err(format!("Unknown precedence: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn stamp_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("photo.jpg");
+        let mut contents = vec![0; 200 * 1024];
+        fs::write(&path, &contents).unwrap();
+
+        let stamp = FileStamp::of(&path, true).unwrap();
+        assert_eq!(stamp.size, contents.len() as u64);
+        assert!(stamp.modified.is_some());
+        assert_eq!(FileStamp::of(&path, false).unwrap().hash, None);
+
+        // Only the start and the end are hashed
+        contents[100 * 1024] = 1;
+        fs::write(&path, &contents).unwrap();
+        assert_eq!(FileStamp::of(&path, true).unwrap().hash, stamp.hash);
+        contents[10] = 1;
+        fs::write(&path, &contents).unwrap();
+        assert_ne!(FileStamp::of(&path, true).unwrap().hash, stamp.hash);
+
+        // Stamps with and without a hash are compared by size and time
+        let changed = FileStamp::of(&path, true).unwrap();
+        assert!(!changed.matches(&stamp));
+        let unhashed = FileStamp::of(&path, false).unwrap();
+        assert!(changed.matches(&unhashed) && unhashed.matches(&changed));
+        assert!(!FileStamp {
+            size: 1,
+            ..unhashed.clone()
+        }
+        .matches(&unhashed));
+    }
+
+    #[test]
+    fn choose_by_precedence() {
+        let metadata = || PhotoMetadata {
+            coordinates: Some((1.0, 2.0)),
+            source: Some(MetadataSource::Embedded),
+            sidecar: Some(((3.0, 4.0), MetadataSource::Xmp)),
+            ..PhotoMetadata::default()
+        };
+        let sidecar = metadata().with_precedence(Precedence::Sidecar);
+        assert_eq!(sidecar.coordinates, Some((3.0, 4.0)));
+        assert_eq!(sidecar.source, Some(MetadataSource::Xmp));
+        let embedded = metadata().with_precedence(Precedence::Embedded);
+        assert_eq!(embedded.coordinates, Some((1.0, 2.0)));
+
+        let record = serde_json::to_value(metadata()).unwrap();
+        assert!(PhotoMetadata::is_current(&record));
+        let merged = serde_json::json!({
+            "coordinates": [3.0, 4.0],
+            "source": "Xmp",
+            "taken_at": null
+        });
+        assert!(!PhotoMetadata::is_current(&merged));
+        let untimed = serde_json::json!({ "coordinates": [1.0, 2.0], "source": "Embedded" });
+        assert!(!PhotoMetadata::is_current(&untimed));
     }
 }
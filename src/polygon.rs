@@ -0,0 +1,232 @@
+use std::{path::Path, str::FromStr};
+
+use geo::{Coord, Geometry, GeometryCollection, LineString, MultiPolygon, Polygon};
+use geojson::GeoJson;
+use wkt::TryFromWkt;
+
+use crate::{
+    geo_file::{self, Syntax},
+    Result,
+};
+
+/// Read Polygon and MultiPolygon geometry from a GeoJSON, KML or WKT file.
+/// Other geometry types in the file are ignored.
+pub fn read_polygons(path: &Path) -> Result<MultiPolygon<f64>> {
+    geo_file::read(
+        path,
+        "polygon",
+        |syntax, contents| match syntax {
+            Syntax::Json => parse_geojson(contents),
+            Syntax::Xml => parse_kml(contents),
+            Syntax::Text => parse_wkt(contents),
+        },
+        |polygons| polygons.0.is_empty(),
+    )
+}
+
+fn parse_geojson(text: &str) -> Result<MultiPolygon<f64>, String> {
+    let geojson = GeoJson::from_str(text).map_err(|e| e.to_string())?;
+    from_geojson(&geojson)
+}
+
+/// Collect polygons from a GeoJSON geometry, feature or feature collection
+pub fn from_geojson(geojson: &GeoJson) -> Result<MultiPolygon<f64>, String> {
+    let collection = GeometryCollection::<f64>::try_from(geojson).map_err(|e| e.to_string())?;
+    let mut polygons = Vec::new();
+    for geometry in collection {
+        collect_polygons(geometry, &mut polygons);
+    }
+    Ok(MultiPolygon(polygons))
+}
+
+fn parse_wkt(text: &str) -> Result<MultiPolygon<f64>, String> {
+    let geometry = Geometry::<f64>::try_from_wkt_str(text.trim()).map_err(|e| e.to_string())?;
+    let mut polygons = Vec::new();
+    collect_polygons(geometry, &mut polygons);
+    Ok(MultiPolygon(polygons))
+}
+
+fn collect_polygons(geometry: Geometry<f64>, polygons: &mut Vec<Polygon<f64>>) {
+    match geometry {
+        Geometry::Polygon(polygon) => polygons.push(polygon),
+        Geometry::MultiPolygon(multi) => polygons.extend(multi),
+        Geometry::Rect(rect) => polygons.push(rect.to_polygon()),
+        Geometry::GeometryCollection(collection) => {
+            for geometry in collection {
+                collect_polygons(geometry, polygons);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Polygons may be nested anywhere, e.g. in MultiGeometry within Placemarks
+// within Folders, so every Polygon element in the document is taken
+fn parse_kml(text: &str) -> Result<MultiPolygon<f64>, String> {
+    let doc = roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+    let mut polygons = Vec::new();
+    for node in doc
+        .descendants()
+        .filter(|node| node.tag_name().name() == "Polygon")
+    {
+        let exterior = match kml_child(node, "outerBoundaryIs") {
+            Some(boundary) => kml_ring(boundary)?,
+            None => None,
+        };
+        let exterior = exterior.ok_or("Polygon without outerBoundaryIs")?;
+        let mut interiors = Vec::new();
+        for boundary in node
+            .children()
+            .filter(|child| child.tag_name().name() == "innerBoundaryIs")
+        {
+            interiors.extend(kml_ring(boundary)?);
+        }
+        polygons.push(Polygon::new(exterior, interiors));
+    }
+    Ok(MultiPolygon(polygons))
+}
+
+fn kml_child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+}
+
+fn kml_ring(boundary: roxmltree::Node) -> Result<Option<LineString<f64>>, String> {
+    match kml_child(boundary, "LinearRing").and_then(|ring| kml_child(ring, "coordinates")) {
+        Some(node) => parse_kml_coordinates(node.text().unwrap_or("")).map(Some),
+        None => Ok(None),
+    }
+}
+
+// Tuples of `lon,lat[,alt]` separated by whitespace
+fn parse_kml_coordinates(text: &str) -> Result<LineString<f64>, String> {
+    text.split_whitespace()
+        .map(|tuple| {
+            let mut values = tuple.split(',').map(|value| value.parse::<f64>());
+            match (values.next(), values.next()) {
+                (Some(Ok(x)), Some(Ok(y))) => Ok(Coord { x, y }),
+                _ => Err(format!("Invalid coordinates {}", tuple)),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(LineString::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo_file::tests::read_temp;
+    use geo::{Contains, Point};
+
+    // Square 0..10 with a hole 4..6, and a separate square 20..21
+    const GEOJSON: &str = r#"{
+  "type": "FeatureCollection",
+  "features": [
+    {
+      "type": "Feature",
+      "properties": {"name": "park"},
+      "geometry": {
+        "type": "Polygon",
+        "coordinates": [
+          [[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+          [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]
+        ]
+      }
+    },
+    {
+      "type": "Feature",
+      "properties": {"name": "entrance"},
+      "geometry": {"type": "Point", "coordinates": [5, 5]}
+    },
+    {
+      "type": "Feature",
+      "properties": {},
+      "geometry": {
+        "type": "MultiPolygon",
+        "coordinates": [[[[20, 20], [21, 20], [21, 21], [20, 21], [20, 20]]]]
+      }
+    }
+  ]
+}"#;
+
+    const KML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2">
+  <Document>
+    <Folder>
+      <Placemark>
+        <MultiGeometry>
+          <Polygon>
+            <outerBoundaryIs>
+              <LinearRing>
+                <coordinates>
+                  0,0,0 10,0,0 10,10,0 0,10,0 0,0,0
+                </coordinates>
+              </LinearRing>
+            </outerBoundaryIs>
+            <innerBoundaryIs>
+              <LinearRing>
+                <coordinates>4,4 6,4 6,6 4,6 4,4</coordinates>
+              </LinearRing>
+            </innerBoundaryIs>
+          </Polygon>
+          <Polygon>
+            <outerBoundaryIs>
+              <LinearRing>
+                <coordinates>20,20 21,20 21,21 20,21 20,20</coordinates>
+              </LinearRing>
+            </outerBoundaryIs>
+          </Polygon>
+        </MultiGeometry>
+      </Placemark>
+    </Folder>
+  </Document>
+</kml>"#;
+
+    const WKT: &str = "MULTIPOLYGON (((0 0, 10 0, 10 10, 0 10, 0 0), (4 4, 6 4, 6 6, 4 6, 4 4)), \
+                       ((20 20, 21 20, 21 21, 20 21, 20 20)))";
+
+    fn assert_shape(polygons: &MultiPolygon<f64>) {
+        // Points are (lon, lat)
+        assert_eq!(polygons.0.len(), 2);
+        assert!(polygons.contains(&Point::new(2.0, 2.0)));
+        assert!(polygons.contains(&Point::new(20.5, 20.5)));
+        assert!(!polygons.contains(&Point::new(5.0, 5.0)));
+        assert!(!polygons.contains(&Point::new(15.0, 15.0)));
+    }
+
+    fn read(name: &str, contents: &str) -> Result<MultiPolygon<f64>> {
+        read_temp(name, contents, read_polygons)
+    }
+
+    #[test]
+    fn read_geojson() {
+        assert_shape(&read("area.geojson", GEOJSON).unwrap());
+    }
+
+    #[test]
+    fn read_kml() {
+        assert_shape(&read("area.kml", KML).unwrap());
+    }
+
+    #[test]
+    fn read_wkt() {
+        assert_shape(&read("area.wkt", WKT).unwrap());
+    }
+
+    #[test]
+    fn read_by_content() {
+        assert_shape(&read("area.txt", GEOJSON).unwrap());
+        assert_shape(&read("area.txt", KML).unwrap());
+        assert_shape(&read("area", WKT).unwrap());
+    }
+
+    #[test]
+    fn read_without_polygon() {
+        assert!(read("point.wkt", "POINT (1 2)").is_err());
+        assert!(read("broken.geojson", "{").is_err());
+        assert!(read("broken.kml", "<kml><Polygon/></kml>").is_err());
+    }
+}
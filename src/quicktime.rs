@@ -0,0 +1,543 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use chrono::{NaiveDate, TimeDelta};
+
+use crate::{CaptureTime, PhotoMetadata};
+
+const APPLE_LOCATION_KEY: &str = "com.apple.quicktime.location.ISO6709";
+const APPLE_CREATION_DATE_KEY: &str = "com.apple.quicktime.creationdate";
+const XYZ: [u8; 4] = [0xa9, b'x', b'y', b'z'];
+
+#[derive(Default)]
+struct Tags {
+    // Priority and ISO 6709 string
+    locations: Vec<(u8, String)>,
+    creation_date: Option<String>,
+    // Seconds since 1904 in UTC
+    mvhd_creation_time: Option<u64>,
+}
+
+/// Read ISO 6709 location and creation time from a QuickTime/ISO-BMFF (mov,
+/// mp4) file
+pub fn read_metadata(path: &Path) -> io::Result<PhotoMetadata> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+    read_metadata_from(&mut reader)
+}
+
+pub fn read_metadata_from<R: Read + Seek>(reader: &mut R) -> io::Result<PhotoMetadata> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut tags = Tags::default();
+    while let Some(header) = read_header(reader, file_end)? {
+        if &header.kind == b"moov" {
+            read_moov(reader, &header, &mut tags)?;
+        }
+        reader.seek(SeekFrom::Start(header.end))?;
+    }
+
+    // Apple keys are written with more precision than ©xyz, so they come first
+    tags.locations.sort_by_key(|(priority, _)| *priority);
+    let coordinates = tags
+        .locations
+        .iter()
+        .find_map(|(_, location)| parse_iso6709(location));
+
+    // Apple creation date keeps the local time, while mvhd is UTC only
+    let taken_at = match tags
+        .creation_date
+        .as_deref()
+        .and_then(CaptureTime::from_iso8601)
+    {
+        Some(taken_at) => Some(taken_at),
+        None => tags.mvhd_creation_time.and_then(mvhd_time),
+    };
+
+    Ok(PhotoMetadata {
+        coordinates,
+        taken_at,
+        ..PhotoMetadata::default()
+    })
+}
+
+// Corrupt files may hold times too far out to represent
+fn mvhd_time(seconds: u64) -> Option<CaptureTime> {
+    let since_1904 = TimeDelta::try_seconds(i64::try_from(seconds).ok()?)?;
+    let local = NaiveDate::from_ymd_opt(1904, 1, 1)?
+        .and_hms_opt(0, 0, 0)?
+        .checked_add_signed(since_1904)?;
+    Some(CaptureTime {
+        local,
+        offset: Some(0),
+    })
+}
+
+pub struct BoxHeader {
+    pub kind: [u8; 4],
+    pub start: u64,
+    pub end: u64,
+}
+
+pub fn read_header<R: Read + Seek>(
+    reader: &mut R,
+    parent_end: u64,
+) -> io::Result<Option<BoxHeader>> {
+    let box_start = reader.stream_position()?;
+    if box_start + 8 > parent_end {
+        return Ok(None);
+    }
+
+    let size = read_u32(reader)? as u64;
+    let mut kind = [0; 4];
+    reader.read_exact(&mut kind)?;
+    let (size, header_len) = match size {
+        // Size extends to the end of the enclosing box
+        0 => (parent_end - box_start, 8),
+        // 64-bit size follows the type
+        1 => (read_u64(reader)?, 16),
+        _ => (size, 8),
+    };
+    let end = box_start
+        .checked_add(size)
+        .ok_or_else(|| invalid_data("Invalid box size"))?;
+    if size < header_len || end > parent_end {
+        return Err(invalid_data("Invalid box size"));
+    }
+
+    Ok(Some(BoxHeader {
+        kind,
+        start: box_start + header_len,
+        end,
+    }))
+}
+
+fn read_moov<R: Read + Seek>(reader: &mut R, moov: &BoxHeader, tags: &mut Tags) -> io::Result<()> {
+    reader.seek(SeekFrom::Start(moov.start))?;
+    while let Some(header) = read_header(reader, moov.end)? {
+        match &header.kind {
+            b"mvhd" => {
+                // Version 1 uses 64-bit times
+                let version = read_u32(reader)? >> 24;
+                let creation_time = if version == 1 {
+                    read_u64(reader)?
+                } else {
+                    read_u32(reader)? as u64
+                };
+                if creation_time != 0 {
+                    tags.mvhd_creation_time = Some(creation_time);
+                }
+            }
+            b"meta" => read_meta(reader, &header, tags)?,
+            b"udta" => read_udta(reader, &header, tags)?,
+            _ => (),
+        }
+        reader.seek(SeekFrom::Start(header.end))?;
+    }
+    Ok(())
+}
+
+fn read_udta<R: Read + Seek>(reader: &mut R, udta: &BoxHeader, tags: &mut Tags) -> io::Result<()> {
+    reader.seek(SeekFrom::Start(udta.start))?;
+    while let Some(header) = read_header(reader, udta.end)? {
+        if header.kind == XYZ {
+            // 16-bit string length and 16-bit language code precede the text
+            let len = read_u16(reader)? as u64;
+            read_u16(reader)?;
+            let len = len.min(header.end.saturating_sub(header.start + 4));
+            let text = read_string(reader, len)?;
+            tags.locations.push((1, text));
+        } else if &header.kind == b"meta" {
+            read_meta(reader, &header, tags)?;
+        }
+        reader.seek(SeekFrom::Start(header.end))?;
+    }
+    Ok(())
+}
+
+fn read_meta<R: Read + Seek>(reader: &mut R, meta: &BoxHeader, tags: &mut Tags) -> io::Result<()> {
+    // QuickTime meta is a plain box while ISO-BMFF meta is a full box with
+    // version and flags. Tell them apart by where the hdlr box starts.
+    // Too short to hold a child box either way
+    if meta.end - meta.start < 8 {
+        return Ok(());
+    }
+    reader.seek(SeekFrom::Start(meta.start))?;
+    let mut peek = [0; 8];
+    reader.read_exact(&mut peek)?;
+    let start = if &peek[4..8] == b"hdlr" {
+        meta.start
+    } else {
+        meta.start + 4
+    };
+
+    let mut keys = Vec::new();
+    let mut items = Vec::new();
+    reader.seek(SeekFrom::Start(start))?;
+    while let Some(header) = read_header(reader, meta.end)? {
+        match &header.kind {
+            b"keys" => keys = read_keys(reader, &header)?,
+            b"ilst" => items = read_ilst(reader, &header)?,
+            _ => (),
+        }
+        reader.seek(SeekFrom::Start(header.end))?;
+    }
+
+    for (kind, value) in items {
+        // Items refer to keys by 1-based index
+        let index = u32::from_be_bytes(kind) as usize;
+        let key = match index {
+            0 => None,
+            _ => keys.get(index - 1).map(String::as_str),
+        };
+        if key == Some(APPLE_LOCATION_KEY) {
+            tags.locations.push((0, value));
+        } else if key == Some(APPLE_CREATION_DATE_KEY) {
+            tags.creation_date = Some(value);
+        } else if kind == XYZ {
+            tags.locations.push((1, value));
+        }
+    }
+    Ok(())
+}
+
+// Keys past the end of the box are left out, as a count too large would
+// otherwise read into the next box
+fn read_keys<R: Read + Seek>(reader: &mut R, keys: &BoxHeader) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    if keys.end - keys.start < 8 {
+        return Ok(names);
+    }
+    // Skip version and flags
+    reader.seek(SeekFrom::Start(keys.start + 4))?;
+    let count = read_u32(reader)?;
+    let mut entry_start = keys.start + 8;
+    for _ in 0..count {
+        if entry_start + 8 > keys.end {
+            break;
+        }
+        let size = read_u32(reader)? as u64;
+        let mut _namespace = [0; 4];
+        reader.read_exact(&mut _namespace)?;
+        if size < 8 {
+            return Err(invalid_data("Invalid key size"));
+        }
+        if size > keys.end - entry_start {
+            break;
+        }
+        names.push(read_string(reader, size - 8)?);
+        entry_start += size;
+    }
+    Ok(names)
+}
+
+fn read_ilst<R: Read + Seek>(
+    reader: &mut R,
+    ilst: &BoxHeader,
+) -> io::Result<Vec<([u8; 4], String)>> {
+    let mut items = Vec::new();
+    reader.seek(SeekFrom::Start(ilst.start))?;
+    while let Some(item) = read_header(reader, ilst.end)? {
+        while let Some(data) = read_header(reader, item.end)? {
+            if &data.kind == b"data" && data.end >= data.start + 8 {
+                // Skip type indicator and locale
+                reader.seek(SeekFrom::Start(data.start + 8))?;
+                let value = read_string(reader, data.end - data.start - 8)?;
+                items.push((item.kind, value));
+                break;
+            }
+            reader.seek(SeekFrom::Start(data.end))?;
+        }
+        reader.seek(SeekFrom::Start(item.end))?;
+    }
+    Ok(items)
+}
+
+/// Parse ISO 6709 string such as `+37.3317-122.0307+010.123/` into lat, lon
+pub fn parse_iso6709(text: &str) -> Option<(f64, f64)> {
+    let text = text.trim().split('/').next()?;
+
+    let mut components: Vec<String> = Vec::new();
+    for c in text.chars() {
+        match c {
+            '+' | '-' => components.push(c.to_string()),
+            '0'..='9' | '.' => components.last_mut()?.push(c),
+            // Coordinate reference system identifier may follow
+            _ => break,
+        }
+    }
+    if components.len() < 2 {
+        return None;
+    }
+
+    let lat = parse_iso6709_component(&components[0], 2)?;
+    let lon = parse_iso6709_component(&components[1], 3)?;
+    if lat.abs() > 90.0 || lon.abs() > 180.0 {
+        return None;
+    }
+    Some((lat, lon))
+}
+
+// Degrees, degrees and minutes, or degrees, minutes and seconds depending on
+// the number of integer digits
+fn parse_iso6709_component(component: &str, deg_digits: usize) -> Option<f64> {
+    let (sign, body) = component.split_at(1);
+    let sign = if sign == "-" { -1.0 } else { 1.0 };
+    let int_len = body.find('.').unwrap_or(body.len());
+
+    let value = if int_len == deg_digits {
+        body.parse::<f64>().ok()?
+    } else if int_len == deg_digits + 2 {
+        let deg: f64 = body[..deg_digits].parse().ok()?;
+        let min: f64 = body[deg_digits..].parse().ok()?;
+        deg + min / 60.0
+    } else if int_len == deg_digits + 4 {
+        let deg: f64 = body[..deg_digits].parse().ok()?;
+        let min: f64 = body[deg_digits..deg_digits + 2].parse().ok()?;
+        let sec: f64 = body[deg_digits + 2..].parse().ok()?;
+        deg + min / 60.0 + sec / 60.0 / 60.0
+    } else {
+        return None;
+    };
+    Some(sign * value)
+}
+
+fn read_string<R: Read>(reader: &mut R, len: u64) -> io::Result<String> {
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf)
+        .trim_end_matches('\0')
+        .to_string())
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    pub(crate) fn make_box(kind: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut buf = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        buf.extend_from_slice(kind);
+        buf.extend_from_slice(content);
+        buf
+    }
+
+    fn make_xyz(location: &str) -> Vec<u8> {
+        let mut content = (location.len() as u16).to_be_bytes().to_vec();
+        content.extend_from_slice(&0x15c7u16.to_be_bytes());
+        content.extend_from_slice(location.as_bytes());
+        make_box(&XYZ, &content)
+    }
+
+    fn make_apple_meta(location: &str) -> Vec<u8> {
+        let hdlr = make_box(b"hdlr", &[0; 24]);
+
+        let mut keys = vec![0, 0, 0, 0];
+        keys.extend_from_slice(&2u32.to_be_bytes());
+        for key in [APPLE_CREATION_DATE_KEY, APPLE_LOCATION_KEY] {
+            keys.extend_from_slice(&((key.len() + 8) as u32).to_be_bytes());
+            keys.extend_from_slice(b"mdta");
+            keys.extend_from_slice(key.as_bytes());
+        }
+        let keys = make_box(b"keys", &keys);
+
+        let mut ilst = Vec::new();
+        for (index, value) in [(1u32, "2024-03-15T10:20:30+0100"), (2u32, location)] {
+            let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+            data.extend_from_slice(value.as_bytes());
+            ilst.extend(make_box(&index.to_be_bytes(), &make_box(b"data", &data)));
+        }
+        let ilst = make_box(b"ilst", &ilst);
+
+        make_box(b"meta", &[hdlr, keys, ilst].concat())
+    }
+
+    pub(crate) fn make_mp4(location: &str) -> Vec<u8> {
+        let ftyp = make_box(b"ftyp", b"isom\0\0\x02\0isomiso2mp41");
+        let udta = make_box(b"udta", &make_xyz(location));
+        let moov = make_box(b"moov", &udta);
+        let mdat = make_box(b"mdat", &[0; 64]);
+        [ftyp, mdat, moov].concat()
+    }
+
+    #[test]
+    fn read_udta_xyz() {
+        let mut reader = Cursor::new(make_mp4("+37.3317-122.0307/"));
+
+        let coords = read_metadata_from(&mut reader).unwrap().coordinates;
+
+        assert_eq!(coords, Some((37.3317, -122.0307)));
+    }
+
+    #[test]
+    fn read_apple_keys_before_xyz() {
+        let ftyp = make_box(b"ftyp", b"qt  \0\0\0\0qt  ");
+        let udta = make_box(b"udta", &make_xyz("+10.0000+010.0000/"));
+        let meta = make_apple_meta("+37.3317-122.0307+010.123/");
+        let moov = make_box(b"moov", &[udta, meta].concat());
+        let mut reader = Cursor::new([ftyp, moov].concat());
+
+        let metadata = read_metadata_from(&mut reader).unwrap();
+
+        assert_eq!(metadata.coordinates, Some((37.3317, -122.0307)));
+        assert_eq!(
+            metadata.taken_at,
+            CaptureTime::from_exif("2024:03:15 10:20:30", Some("+01:00"))
+        );
+    }
+
+    #[test]
+    fn read_mvhd_creation_time() {
+        // Version 0 with 32-bit times
+        let mut mvhd = vec![0; 4];
+        mvhd.extend_from_slice(&3_793_339_230u32.to_be_bytes());
+        mvhd.extend_from_slice(&[0; 92]);
+        let moov = make_box(b"moov", &make_box(b"mvhd", &mvhd));
+        let mut reader = Cursor::new(moov);
+
+        let taken_at = read_metadata_from(&mut reader).unwrap().taken_at;
+
+        assert_eq!(
+            taken_at,
+            CaptureTime::from_exif("2024:03:15 09:20:30", Some("Z"))
+        );
+    }
+
+    #[test]
+    fn read_out_of_range_mvhd_creation_time() {
+        // Version 1 with 64-bit times
+        let mut mvhd = vec![1, 0, 0, 0];
+        mvhd.extend_from_slice(&(1u64 << 62).to_be_bytes());
+        mvhd.extend_from_slice(&[0; 104]);
+        let moov = make_box(b"moov", &make_box(b"mvhd", &mvhd));
+        let mut reader = Cursor::new(moov);
+
+        let taken_at = read_metadata_from(&mut reader).unwrap().taken_at;
+
+        assert!(taken_at.is_none());
+    }
+
+    #[test]
+    fn read_itunes_meta_in_udta() {
+        let hdlr = make_box(b"hdlr", &[0; 24]);
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        data.extend_from_slice(b"-33.8568+151.2153/");
+        let ilst = make_box(b"ilst", &make_box(&XYZ, &make_box(b"data", &data)));
+        // Full box: version and flags come first
+        let meta = make_box(b"meta", &[vec![0; 4], hdlr, ilst].concat());
+        let moov = make_box(b"moov", &make_box(b"udta", &meta));
+        let mut reader = Cursor::new(moov);
+
+        let coords = read_metadata_from(&mut reader).unwrap().coordinates;
+
+        assert_eq!(coords, Some((-33.8568, 151.2153)));
+    }
+
+    #[test]
+    fn read_no_location() {
+        let moov = make_box(b"moov", &make_box(b"udta", &[]));
+        let mut reader = Cursor::new(moov);
+
+        let coords = read_metadata_from(&mut reader).unwrap().coordinates;
+
+        assert!(coords.is_none());
+    }
+
+    #[test]
+    fn read_keys_within_box() {
+        let hdlr = make_box(b"hdlr", &[0; 24]);
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        data.extend_from_slice(b"+37.3317-122.0307/");
+        let ilst = make_box(
+            b"ilst",
+            &make_box(&1u32.to_be_bytes(), &make_box(b"data", &data)),
+        );
+        // Count of two while the box, last in the file, holds one key
+        let mut keys = vec![0, 0, 0, 0];
+        keys.extend_from_slice(&2u32.to_be_bytes());
+        keys.extend_from_slice(&((APPLE_LOCATION_KEY.len() + 8) as u32).to_be_bytes());
+        keys.extend_from_slice(b"mdta");
+        keys.extend_from_slice(APPLE_LOCATION_KEY.as_bytes());
+        let keys = make_box(b"keys", &keys);
+        let meta = make_box(b"meta", &[hdlr, ilst, keys].concat());
+        let mut reader = Cursor::new(make_box(b"moov", &meta));
+
+        let coords = read_metadata_from(&mut reader).unwrap().coordinates;
+
+        assert_eq!(coords, Some((37.3317, -122.0307)));
+
+        // Empty meta box, last in the file
+        let udta = make_box(b"udta", &make_xyz("+37.3317-122.0307/"));
+        let moov = make_box(b"moov", &[udta, make_box(b"meta", &[])].concat());
+        let mut reader = Cursor::new(moov);
+
+        let coords = read_metadata_from(&mut reader).unwrap().coordinates;
+
+        assert_eq!(coords, Some((37.3317, -122.0307)));
+    }
+
+    #[test]
+    fn read_invalid_box_size() {
+        let mut moov = make_box(b"moov", &[]);
+        moov[3] = 0xff;
+        let mut reader = Cursor::new(moov);
+
+        assert!(read_metadata_from(&mut reader).is_err());
+    }
+
+    #[test]
+    fn read_overflowing_box_size() {
+        let ftyp = make_box(b"ftyp", b"isom\0\0\x02\0isom");
+        let mut moov = 1u32.to_be_bytes().to_vec();
+        moov.extend_from_slice(b"moov");
+        moov.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        let mut reader = Cursor::new([ftyp, moov].concat());
+
+        let err = read_metadata_from(&mut reader).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_iso6709_formats() {
+        assert_eq!(parse_iso6709("+40.75-073.99/"), Some((40.75, -73.99)));
+        assert_eq!(
+            parse_iso6709("+4045-07359/"),
+            Some((40.75, -73.98333333333333))
+        );
+        assert_eq!(
+            parse_iso6709("+404500.0-0735900.0+12.5CRSWGS_84/"),
+            Some((40.75, -73.98333333333333))
+        );
+        assert_eq!(parse_iso6709("+40.75"), None);
+        assert_eq!(parse_iso6709("+95.0+010.0/"), None);
+        assert_eq!(parse_iso6709("garbage"), None);
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    coords_from_exif, gps_time_from_exif, original_time_from_exif, quicktime, CaptureTime, Format,
+    PhotoMetadata,
+};
+
+// Panasonic JpgFromRaw, the preview holding Exif and GPS of an RW2 file
+const RW2_JPG_FROM_RAW: exif::Tag = exif::Tag(exif::Context::Tiff, 0x002e);
+
+// Canon metadata box in moov holding CMT1-CMT4 TIFF blobs
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+/// Read Exif from RAW formats not handled by kamadak-exif as is
+pub fn read_exif(path: &Path, format: Format) -> Result<exif::Exif, exif::Error> {
+    match format {
+        Format::Orf => read_tiff_with_magic(path),
+        Format::Rw2 => {
+            // IFD0 only has raw data tags, apart from in some synthetic files
+            let exif = read_tiff_with_magic(path)?;
+            match exif.get_field(RW2_JPG_FROM_RAW, exif::In::PRIMARY) {
+                Some(exif::Field {
+                    value: exif::Value::Undefined(jpeg, _),
+                    ..
+                }) => exif::Reader::new().read_from_container(&mut Cursor::new(jpeg)),
+                _ => Ok(exif),
+            }
+        }
+        Format::Raf => {
+            let jpeg = read_raf_jpeg(path)?;
+            exif::Reader::new().read_from_container(&mut Cursor::new(jpeg))
+        }
+        _ => Err(exif::Error::InvalidFormat("Not a RAW format")),
+    }
+}
+
+// Plain TIFF apart from the magic number
+fn read_tiff_with_magic(path: &Path) -> Result<exif::Exif, exif::Error> {
+    let mut buf = fs::read(path)?;
+    if buf.len() < 4 {
+        return Err(exif::Error::InvalidFormat("Broken RAW file"));
+    }
+    let magic: &[u8] = if &buf[..2] == b"MM" { b"\0*" } else { b"*\0" };
+    buf[2..4].copy_from_slice(magic);
+    exif::Reader::new().read_raw(buf)
+}
+
+// JPEG offset and length are stored at fixed positions after the header
+fn read_raf_jpeg(path: &Path) -> Result<Vec<u8>, exif::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = [0; 92];
+    reader.read_exact(&mut header)?;
+    let offset = u32::from_be_bytes(header[84..88].try_into().unwrap());
+    let len = u32::from_be_bytes(header[88..92].try_into().unwrap());
+
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    let mut jpeg = Vec::new();
+    reader.take(len as u64).read_to_end(&mut jpeg)?;
+    if jpeg.len() != len as usize {
+        return Err(exif::Error::InvalidFormat("Truncated RAF preview"));
+    }
+    Ok(jpeg)
+}
+
+/// Read Exif IFD from the CMT2 box and GPS IFD from the CMT4 box of a CR3
+/// file
+pub fn read_cr3_metadata(path: &Path) -> Result<PhotoMetadata, exif::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    // Both are stored as IFD0, so their tags are read in TIFF context
+    let in_tiff = |tag: exif::Tag| exif::Tag(exif::Context::Tiff, tag.number());
+    let read_cmt = |reader: &mut BufReader<File>, kind| match find_cmt(reader, kind)? {
+        Some(buf) => exif::Reader::new().read_raw(buf).map(Some),
+        None => Ok(None),
+    };
+
+    let exif = read_cmt(&mut reader, b"CMT2")?;
+    let gps = read_cmt(&mut reader, b"CMT4")?;
+    Ok(PhotoMetadata {
+        coordinates: gps.as_ref().and_then(|gps| coords_from_exif(gps, in_tiff)),
+        taken_at: CaptureTime::merge(
+            exif.as_ref()
+                .and_then(|exif| original_time_from_exif(exif, in_tiff)),
+            gps.as_ref()
+                .and_then(|gps| gps_time_from_exif(gps, in_tiff)),
+        ),
+        ..PhotoMetadata::default()
+    })
+}
+
+fn find_cmt<R: Read + Seek>(
+    reader: &mut R,
+    kind: &[u8; 4],
+) -> Result<Option<Vec<u8>>, exif::Error> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    while let Some(header) = quicktime::read_header(reader, file_end)? {
+        if &header.kind == b"moov" {
+            while let Some(child) = quicktime::read_header(reader, header.end)? {
+                if &child.kind == b"uuid" {
+                    let mut uuid = [0; 16];
+                    reader.read_exact(&mut uuid)?;
+                    if uuid == CANON_UUID {
+                        return find_cmt_in_uuid(reader, child.end, kind);
+                    }
+                }
+                reader.seek(SeekFrom::Start(child.end))?;
+            }
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Start(header.end))?;
+    }
+    Ok(None)
+}
+
+fn find_cmt_in_uuid<R: Read + Seek>(
+    reader: &mut R,
+    uuid_end: u64,
+    kind: &[u8; 4],
+) -> Result<Option<Vec<u8>>, exif::Error> {
+    while let Some(header) = quicktime::read_header(reader, uuid_end)? {
+        if &header.kind == kind {
+            let mut buf = Vec::new();
+            reader
+                .take(header.end - header.start)
+                .read_to_end(&mut buf)?;
+            return Ok(Some(buf));
+        }
+        reader.seek(SeekFrom::Start(header.end))?;
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{quicktime::tests::make_box, tests::make_exif_tiff};
+    use tempfile::tempdir;
+
+    fn assert_coords(coords: Option<(f64, f64)>, expected: (f64, f64)) {
+        let (lat, lon) = coords.unwrap();
+        assert!((lat - expected.0).abs() < 1e-6);
+        assert!((lon - expected.1).abs() < 1e-6);
+    }
+
+    fn read_coords(contents: &[u8], format: Format) -> Option<(f64, f64)> {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("image.raw");
+        fs::write(&path, contents).unwrap();
+        let exif = read_exif(&path, format).unwrap();
+        coords_from_exif(&exif, |tag| tag)
+    }
+
+    fn make_exif_jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut jpeg = b"\xff\xd8\xff\xe1".to_vec();
+        jpeg.extend_from_slice(&(tiff.len() as u16 + 8).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(tiff);
+        jpeg.extend_from_slice(b"\xff\xd9");
+        jpeg
+    }
+
+    #[test]
+    fn read_orf() {
+        let mut orf = make_exif_tiff((35.6586, 139.7454), false);
+        orf[2..4].copy_from_slice(b"OR");
+
+        assert_coords(read_coords(&orf, Format::Orf), (35.6586, 139.7454));
+    }
+
+    #[test]
+    fn read_rw2() {
+        // Sensor size in IFD0, and Exif with GPS only in the JPEG preview
+        let jpeg = make_exif_jpeg(&make_exif_tiff((48.8584, 2.2945), true));
+        let fields = [
+            (0x0002, exif::Value::Short(vec![5216])),
+            (0x0003, exif::Value::Short(vec![3912])),
+            (0x002e, exif::Value::Undefined(jpeg, 0)),
+        ]
+        .map(|(number, value)| exif::Field {
+            tag: exif::Tag(exif::Context::Tiff, number),
+            ifd_num: exif::In::PRIMARY,
+            value,
+        });
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields.iter() {
+            writer.push_field(field);
+        }
+        let mut rw2 = Cursor::new(Vec::new());
+        writer.write(&mut rw2, true).unwrap();
+        let mut rw2 = rw2.into_inner();
+        rw2[2..4].copy_from_slice(b"U\0");
+
+        assert_coords(read_coords(&rw2, Format::Rw2), (48.8584, 2.2945));
+    }
+
+    #[test]
+    fn read_raf() {
+        let jpeg = make_exif_jpeg(&make_exif_tiff((-22.9519, -43.2105), false));
+
+        let mut raf = b"FUJIFILMCCD-RAW 0201FF383501".to_vec();
+        raf.resize(84, 0);
+        raf.extend_from_slice(&100u32.to_be_bytes());
+        raf.extend_from_slice(&(jpeg.len() as u32).to_be_bytes());
+        raf.resize(100, 0);
+        raf.extend_from_slice(&jpeg);
+
+        assert_coords(read_coords(&raf, Format::Raf), (-22.9519, -43.2105));
+    }
+
+    #[test]
+    fn read_cr3() {
+        // GPS IFD as IFD0: GPS tag numbers in TIFF context
+        let gps = make_exif_tiff((51.5007, -0.1246), true);
+        let exif = exif::Reader::new().read_raw(gps).unwrap();
+        let fields: Vec<_> = exif
+            .fields()
+            .map(|field| exif::Field {
+                tag: exif::Tag(exif::Context::Tiff, field.tag.number()),
+                ifd_num: exif::In::PRIMARY,
+                value: field.value.clone(),
+            })
+            .collect();
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields.iter() {
+            writer.push_field(field);
+        }
+        let mut cmt4 = Cursor::new(Vec::new());
+        writer.write(&mut cmt4, true).unwrap();
+
+        let ftyp = make_box(b"ftyp", b"crx \0\0\0\x01crx isom");
+        let uuid = make_box(
+            b"uuid",
+            &[CANON_UUID.to_vec(), make_box(b"CMT4", &cmt4.into_inner())].concat(),
+        );
+        let moov = make_box(b"moov", &uuid);
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("image.cr3");
+        fs::write(&path, [ftyp, moov].concat()).unwrap();
+
+        let coords = read_cr3_metadata(&path).unwrap().coordinates;
+
+        assert_coords(coords, (51.5007, -0.1246));
+    }
+
+    #[test]
+    fn read_cr3_without_gps() {
+        let ftyp = make_box(b"ftyp", b"crx \0\0\0\x01crx isom");
+        let moov = make_box(b"moov", &make_box(b"uuid", &CANON_UUID));
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("image.cr3");
+        fs::write(&path, [ftyp, moov].concat()).unwrap();
+
+        let coords = read_cr3_metadata(&path).unwrap().coordinates;
+
+        assert!(coords.is_none());
+    }
+}
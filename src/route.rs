@@ -0,0 +1,310 @@
+use std::{path::Path, str::FromStr};
+
+use geo::{Coord, Geometry, GeometryCollection, LineString};
+use geojson::GeoJson;
+
+use crate::{
+    compute_distance,
+    geo_file::{self, Syntax},
+    Result,
+};
+
+/// Polylines of the tracks or segments of a route with the distance covered
+/// at each point. Gaps between polylines are not part of the route.
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// Points of each polyline as `(lat, lon)`
+    parts: Vec<Vec<(f64, f64)>>,
+    /// Meters from the start to each point, not counting the gaps
+    covered: Vec<Vec<f64>>,
+}
+
+impl Route {
+    pub fn new(points: Vec<(f64, f64)>) -> Route {
+        Route::from_parts(vec![points])
+    }
+
+    /// Route of separate polylines, in the order they are covered
+    pub fn from_parts(parts: Vec<Vec<(f64, f64)>>) -> Route {
+        let parts: Vec<_> = parts.into_iter().filter(|part| !part.is_empty()).collect();
+        let mut total = 0.0;
+        let covered = parts
+            .iter()
+            .map(|points| {
+                let mut covered = Vec::with_capacity(points.len());
+                for (i, point) in points.iter().enumerate() {
+                    if i > 0 {
+                        total += compute_distance(points[i - 1], *point);
+                    }
+                    covered.push(total);
+                }
+                covered
+            })
+            .collect();
+        Route { parts, covered }
+    }
+
+    /// Total length in meters
+    pub fn length(&self) -> f64 {
+        self.covered
+            .last()
+            .and_then(|covered| covered.last())
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    pub fn start(&self) -> Option<(f64, f64)> {
+        self.parts
+            .first()
+            .and_then(|points| points.first())
+            .copied()
+    }
+
+    /// Distance in meters from coordinates to the nearest point of the route
+    /// and how far along the route that point is
+    pub fn locate(&self, coords: (f64, f64)) -> (f64, f64) {
+        self.parts
+            .iter()
+            .zip(self.covered.iter())
+            .map(|(points, covered)| locate_on_polyline(points, covered, coords))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap_or((f64::INFINITY, 0.0))
+    }
+}
+
+fn locate_on_polyline(points: &[(f64, f64)], covered: &[f64], coords: (f64, f64)) -> (f64, f64) {
+    if points.len() == 1 {
+        return (compute_distance(points[0], coords), covered[0]);
+    }
+    points
+        .windows(2)
+        .zip(covered.iter())
+        .map(|(segment, covered)| {
+            let (nearest, fraction) = nearest_on_segment(segment[0], segment[1], coords);
+            let segment_len = compute_distance(segment[0], segment[1]);
+            (
+                compute_distance(nearest, coords),
+                covered + fraction * segment_len,
+            )
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .unwrap_or((f64::INFINITY, 0.0))
+}
+
+// Project onto the segment in an equirectangular plane around the point,
+// which is accurate enough for segments of a recorded track
+fn nearest_on_segment(
+    start: (f64, f64),
+    end: (f64, f64),
+    (lat, lon): (f64, f64),
+) -> ((f64, f64), f64) {
+    let scale = lat.to_radians().cos();
+    let project = |(lat1, lon1): (f64, f64)| (wrap(lon1 - lon) * scale, lat1 - lat);
+    let (ax, ay) = project(start);
+    let (bx, by) = project(end);
+    let (dx, dy) = (bx - ax, by - ay);
+    let len2 = dx * dx + dy * dy;
+    let fraction = if len2 == 0.0 {
+        0.0
+    } else {
+        (-(ax * dx + ay * dy) / len2).clamp(0.0, 1.0)
+    };
+    let nearest = (
+        start.0 + fraction * (end.0 - start.0),
+        wrap(start.1 + fraction * wrap(end.1 - start.1)),
+    );
+    (nearest, fraction)
+}
+
+// Longitude difference or value into -180..180
+fn wrap(lon: f64) -> f64 {
+    if lon > 180.0 {
+        lon - 360.0
+    } else if lon < -180.0 {
+        lon + 360.0
+    } else {
+        lon
+    }
+}
+
+/// Read a route from GPX track or route points, or GeoJSON LineString and
+/// MultiLineString. Each track segment, route and line is a polyline of its
+/// own, in file order.
+pub fn read_route(path: &Path) -> Result<Route> {
+    let parts = geo_file::read(
+        path,
+        "route",
+        |syntax, contents| match syntax {
+            Syntax::Json => parse_geojson(contents),
+            Syntax::Xml | Syntax::Text => parse_gpx(contents),
+        },
+        |parts| parts.iter().all(Vec::is_empty),
+    )?;
+    Ok(Route::from_parts(parts))
+}
+
+// Tracks are preferred over planned routes when a file has both
+fn parse_gpx(text: &str) -> Result<Vec<Vec<(f64, f64)>>, String> {
+    let doc = roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+    let parts_of = |part: &str, name: &str| {
+        doc.descendants()
+            .filter(|node| node.tag_name().name() == part)
+            .map(|part| {
+                part.children()
+                    .filter(|node| node.tag_name().name() == name)
+                    .map(|node| {
+                        let attribute =
+                            |key| node.attribute(key).and_then(|v| f64::from_str(v).ok());
+                        match (attribute("lat"), attribute("lon")) {
+                            (Some(lat), Some(lon)) => Ok((lat, lon)),
+                            _ => Err(format!("Invalid {} at {}", name, node.range().start)),
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let parts = parts_of("trkseg", "trkpt")?;
+    if parts.iter().all(Vec::is_empty) {
+        parts_of("rte", "rtept")
+    } else {
+        Ok(parts)
+    }
+}
+
+fn parse_geojson(text: &str) -> Result<Vec<Vec<(f64, f64)>>, String> {
+    let geojson = GeoJson::from_str(text).map_err(|e| e.to_string())?;
+    let collection = GeometryCollection::<f64>::try_from(&geojson).map_err(|e| e.to_string())?;
+    let mut lines = Vec::new();
+    for geometry in collection {
+        collect_lines(geometry, &mut lines);
+    }
+    Ok(lines
+        .iter()
+        .map(|line| line.coords().map(|Coord { x, y }| (*y, *x)).collect())
+        .collect())
+}
+
+fn collect_lines(geometry: Geometry<f64>, lines: &mut Vec<LineString<f64>>) {
+    match geometry {
+        Geometry::LineString(line) => lines.push(line),
+        Geometry::MultiLineString(multi) => lines.extend(multi),
+        Geometry::GeometryCollection(collection) => {
+            for geometry in collection {
+                collect_lines(geometry, lines);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo_file::tests::read_temp;
+
+    const GPX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+  <rte>
+    <rtept lat="10.0" lon="10.0"/>
+  </rte>
+  <trk>
+    <name>Walk</name>
+    <trkseg>
+      <trkpt lat="0.0" lon="0.0"><ele>10</ele></trkpt>
+      <trkpt lat="0.0" lon="0.01"/>
+    </trkseg>
+    <trkseg>
+      <trkpt lat="0.01" lon="0.01"/>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+    fn read(name: &str, contents: &str) -> Result<Route> {
+        read_temp(name, contents, read_route)
+    }
+
+    #[test]
+    fn read_gpx_track() {
+        let route = read("walk.gpx", GPX).unwrap();
+
+        assert_eq!(
+            route.parts,
+            [vec![(0.0, 0.0), (0.0, 0.01)], vec![(0.01, 0.01)]]
+        );
+        assert!((route.length() - 1111.95).abs() < 1.0);
+
+        let route = read(
+            "plan.gpx",
+            r#"<gpx><rte><rtept lat="1" lon="2"/></rte></gpx>"#,
+        );
+        assert_eq!(route.unwrap().parts, [vec![(1.0, 2.0)]]);
+        assert!(read("empty.gpx", "<gpx/>").is_err());
+        assert!(read("broken.gpx", r#"<gpx><trkpt lat="a" lon="2"/></gpx>"#).is_err());
+    }
+
+    #[test]
+    fn read_geojson_line() {
+        let geojson = r#"{
+  "type": "Feature",
+  "properties": {},
+  "geometry": {
+    "type": "MultiLineString",
+    "coordinates": [[[0.0, 0.0], [0.01, 0.0]], [[0.01, 0.01]]]
+  }
+}"#;
+
+        let route = read("walk.geojson", geojson).unwrap();
+
+        assert_eq!(
+            route.parts,
+            [vec![(0.0, 0.0), (0.0, 0.01)], vec![(0.01, 0.01)]]
+        );
+    }
+
+    #[test]
+    fn locate_along_route() {
+        let route = Route::new(vec![(0.0, 0.0), (0.0, 0.01), (0.01, 0.01)]);
+
+        // About 111 m north of the middle of the first segment
+        let (distance, position) = route.locate((0.001, 0.005));
+        assert!((distance - 111.2).abs() < 1.0, "{}", distance);
+        assert!((position - 556.0).abs() < 1.0, "{}", position);
+
+        // Beyond the end
+        let (distance, position) = route.locate((0.02, 0.01));
+        assert!((distance - 1112.0).abs() < 1.0, "{}", distance);
+        assert!((position - route.length()).abs() < 1e-6);
+
+        let (distance, position) = Route::new(vec![(0.0, 0.0)]).locate((0.0, 0.0));
+        assert_eq!((distance, position), (0.0, 0.0));
+    }
+
+    #[test]
+    fn locate_along_separate_parts() {
+        let route = Route::from_parts(vec![
+            vec![(0.0, 0.0), (0.0, 0.01)],
+            vec![(0.01, 0.01), (0.01, 0.02)],
+        ]);
+
+        // Between the parts, where joining them would pass
+        let (distance, _) = route.locate((0.005, 0.01));
+        assert!((distance - 556.0).abs() < 1.0, "{}", distance);
+
+        // The second part starts where the first one ended
+        let (distance, position) = route.locate((0.01, 0.015));
+        assert!(distance < 1.0, "{}", distance);
+        assert!((position - 1.5 * 1111.95).abs() < 1.0, "{}", position);
+        assert!((route.length() - 2.0 * 1111.95).abs() < 1.0);
+    }
+
+    #[test]
+    fn locate_across_antimeridian() {
+        let route = Route::new(vec![(0.0, 179.99), (0.0, -179.99)]);
+
+        let (distance, position) = route.locate((0.0, 180.0));
+
+        assert!(distance < 1.0, "{}", distance);
+        assert!((position - route.length() / 2.0).abs() < 1.0);
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+// Takeout truncates sidecar names to this many characters before `.json`
+const MAX_NAME_LEN: usize = 46;
+const SUPPLEMENTAL: &str = ".supplemental-metadata";
+
+/// Find the Google Takeout JSON sidecar of a photo
+pub fn find_sidecar(path: &Path) -> Option<PathBuf> {
+    let dir = path.parent()?;
+    let file_name = path.file_name()?.to_str()?;
+    sidecar_names(file_name)
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+// Candidates for `IMG_0001(1)-edited.jpg` include `IMG_0001.jpg(1).json`,
+// i.e. the duplicate counter moves after the extension and edited copies share
+// the sidecar of the original
+fn sidecar_names(file_name: &str) -> Vec<String> {
+    let (stem, ext) = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, Some(ext)),
+        _ => (file_name, None),
+    };
+    let stem = stem.strip_suffix("-edited").unwrap_or(stem);
+    let (stem, counter) = split_counter(stem);
+    let full_name = match ext {
+        Some(ext) => format!("{}.{}", stem, ext),
+        None => stem.to_string(),
+    };
+
+    let mut names = Vec::new();
+    for base in [
+        full_name.clone() + SUPPLEMENTAL,
+        full_name,
+        stem.to_string(),
+    ] {
+        let name = format!("{}{}.json", truncate(&base, MAX_NAME_LEN), counter);
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// Split `IMG_0001(1)` into `IMG_0001` and `(1)`
+fn split_counter(stem: &str) -> (&str, &str) {
+    if let Some(open) = stem.rfind('(') {
+        let counter = &stem[open..];
+        if let Some(digits) = counter[1..].strip_suffix(')') {
+            if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+                return (&stem[..open], counter);
+            }
+        }
+    }
+    (stem, "")
+}
+
+fn truncate(text: &str, max_len: usize) -> &str {
+    match text.char_indices().nth(max_len) {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
+
+pub fn read_location(path: &Path) -> io::Result<Option<(f64, f64)>> {
+    let contents = fs::read_to_string(path)?;
+    let value: Value = serde_json::from_str(&contents)?;
+    Ok(parse_location(&value))
+}
+
+/// Read `geoData`, falling back to `geoDataExif`. Takeout writes zeros when
+/// the location is unknown.
+pub fn parse_location(value: &Value) -> Option<(f64, f64)> {
+    ["geoData", "geoDataExif"].iter().find_map(|key| {
+        let lat = value[key]["latitude"].as_f64()?;
+        let lon = value[key]["longitude"].as_f64()?;
+        if lat == 0.0 && lon == 0.0 {
+            None
+        } else {
+            Some((lat, lon))
+        }
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    pub(crate) const SIDECAR: &str = r#"{
+  "title": "IMG_0001.jpg",
+  "geoData": {
+    "latitude": 0.0,
+    "longitude": 0.0,
+    "altitude": 0.0,
+    "latitudeSpan": 0.0,
+    "longitudeSpan": 0.0
+  },
+  "geoDataExif": {
+    "latitude": 48.8583701,
+    "longitude": 2.2944813,
+    "altitude": 35.0,
+    "latitudeSpan": 0.0,
+    "longitudeSpan": 0.0
+  }
+}"#;
+
+    #[test]
+    fn parse_geo_data() {
+        let mut value: Value = serde_json::from_str(SIDECAR).unwrap();
+
+        assert_eq!(parse_location(&value), Some((48.8583701, 2.2944813)));
+
+        value["geoData"]["latitude"] = 51.5007.into();
+        value["geoData"]["longitude"] = (-0.1246).into();
+        assert_eq!(parse_location(&value), Some((51.5007, -0.1246)));
+
+        assert_eq!(parse_location(&serde_json::json!({"title": "a.jpg"})), None);
+    }
+
+    #[test]
+    fn sidecar_name_rules() {
+        assert_eq!(
+            sidecar_names("IMG_0001.jpg"),
+            [
+                "IMG_0001.jpg.supplemental-metadata.json",
+                "IMG_0001.jpg.json",
+                "IMG_0001.json"
+            ]
+        );
+        assert!(sidecar_names("IMG_0001(1).jpg").contains(&"IMG_0001.jpg(1).json".to_string()));
+        assert!(sidecar_names("IMG_0001-edited.jpg").contains(&"IMG_0001.jpg.json".to_string()));
+        assert!(
+            sidecar_names("IMG_0001(2)-edited.jpg").contains(&"IMG_0001.jpg(2).json".to_string())
+        );
+
+        let long_name = "Screenshot_20240301-123456_Some Long App Name.png";
+        assert_eq!(
+            sidecar_names(long_name),
+            [
+                "Screenshot_20240301-123456_Some Long App Name..json",
+                "Screenshot_20240301-123456_Some Long App Name.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn split_counter_only_digits() {
+        assert_eq!(split_counter("IMG(12)"), ("IMG", "(12)"));
+        assert_eq!(split_counter("IMG(a)"), ("IMG(a)", ""));
+        assert_eq!(split_counter("IMG()"), ("IMG()", ""));
+        assert_eq!(split_counter("IMG("), ("IMG(", ""));
+    }
+
+    #[test]
+    fn find_duplicate_sidecar() {
+        let temp_dir = tempdir().unwrap();
+        let photo = temp_dir.path().join("IMG_0001(1).jpg");
+        fs::write(&photo, b"").unwrap();
+        fs::write(temp_dir.path().join("IMG_0001.jpg.json"), SIDECAR).unwrap();
+
+        assert_eq!(find_sidecar(&photo), None);
+
+        let sidecar = temp_dir.path().join("IMG_0001.jpg(1).json");
+        fs::write(&sidecar, SIDECAR).unwrap();
+
+        assert_eq!(find_sidecar(&photo), Some(sidecar.clone()));
+        assert_eq!(
+            read_location(&sidecar).unwrap(),
+            Some((48.8583701, 2.2944813))
+        );
+    }
+}
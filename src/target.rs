@@ -0,0 +1,180 @@
+use std::{path::Path, str::FromStr};
+
+use geojson::{feature::Id, GeoJson};
+
+use crate::{
+    geo_file::{self, Syntax},
+    Result,
+};
+
+/// Named location to measure distances from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub name: String,
+    /// `(lat, lon)`
+    pub coords: (f64, f64),
+}
+
+impl Target {
+    /// Target named after its coordinates
+    pub fn at(coords: (f64, f64)) -> Target {
+        Target {
+            name: format!("{},{}", coords.0, coords.1),
+            coords,
+        }
+    }
+}
+
+/// Distance from a photo to one of several targets
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetDistance {
+    pub name: String,
+    pub distance: f64,
+    /// Whether the photo is between the min distance and the radius of the
+    /// target
+    pub matched: bool,
+}
+
+/// Read targets from a CSV of `name,lat,lon` or `lat,lon` rows, or from
+/// GeoJSON Point features named by their `name` property
+pub fn read_targets(path: &Path) -> Result<Vec<Target>> {
+    geo_file::read(
+        path,
+        "target",
+        |syntax, contents| match syntax {
+            Syntax::Json => parse_geojson(contents),
+            Syntax::Xml | Syntax::Text => parse_csv(contents),
+        },
+        |targets| targets.is_empty(),
+    )
+}
+
+// Names may contain commas, so coordinates are taken from the end of a row.
+// A first row without coordinates is a header.
+fn parse_csv(text: &str) -> Result<Vec<Target>, String> {
+    let mut targets = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.rsplitn(3, ',').map(str::trim);
+        let lon = fields.next().and_then(|lon| f64::from_str(lon).ok());
+        let lat = fields.next().and_then(|lat| f64::from_str(lat).ok());
+        let name = fields.next().map(|name| name.trim_matches('"'));
+        match (lat, lon) {
+            (Some(lat), Some(lon)) => targets.push(match name {
+                Some(name) if !name.is_empty() => Target {
+                    name: name.to_string(),
+                    coords: (lat, lon),
+                },
+                _ => Target::at((lat, lon)),
+            }),
+            _ if targets.is_empty() && i == 0 => continue,
+            _ => return Err(format!("Expected name,lat,lon at line {}", i + 1)),
+        }
+    }
+    Ok(targets)
+}
+
+fn parse_geojson(text: &str) -> Result<Vec<Target>, String> {
+    let features = match GeoJson::from_str(text).map_err(|e| e.to_string())? {
+        GeoJson::FeatureCollection(collection) => collection.features,
+        GeoJson::Feature(feature) => vec![feature],
+        GeoJson::Geometry(_) => return Err("Expected features".to_string()),
+    };
+    let mut targets = Vec::new();
+    for feature in features {
+        let coords = match feature.geometry.as_ref().map(|geometry| &geometry.value) {
+            Some(geojson::Value::Point(point)) if point.len() >= 2 => (point[1], point[0]),
+            _ => continue,
+        };
+        let name = feature
+            .property("name")
+            .and_then(|name| name.as_str())
+            .map(str::to_string)
+            .or_else(|| match &feature.id {
+                Some(Id::String(id)) => Some(id.clone()),
+                Some(Id::Number(id)) => Some(id.to_string()),
+                None => None,
+            });
+        targets.push(match name {
+            Some(name) => Target { name, coords },
+            None => Target::at(coords),
+        });
+    }
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo_file::tests::read_temp;
+
+    fn read(name: &str, contents: &str) -> Result<Vec<Target>> {
+        read_temp(name, contents, read_targets)
+    }
+
+    #[test]
+    fn read_csv() {
+        let csv = "name,lat,lon\n\
+                   Eiffel Tower,48.8584,2.2945\n\
+                   \"Big Ben, London\", 51.5007, -0.1246\n\
+                   \n\
+                   35.6586,139.7454\n";
+
+        let targets = read("targets.csv", csv).unwrap();
+
+        assert_eq!(
+            targets,
+            [
+                Target {
+                    name: "Eiffel Tower".to_string(),
+                    coords: (48.8584, 2.2945)
+                },
+                Target {
+                    name: "Big Ben, London".to_string(),
+                    coords: (51.5007, -0.1246)
+                },
+                Target {
+                    name: "35.6586,139.7454".to_string(),
+                    coords: (35.6586, 139.7454)
+                },
+            ]
+        );
+        assert!(read("broken.csv", "a,1,2\nb,x,2\n").is_err());
+        assert!(read("empty.csv", "name,lat,lon\n").is_err());
+    }
+
+    #[test]
+    fn read_geojson_points() {
+        let geojson = r#"{
+  "type": "FeatureCollection",
+  "features": [
+    {
+      "type": "Feature",
+      "properties": {"name": "Eiffel Tower"},
+      "geometry": {"type": "Point", "coordinates": [2.2945, 48.8584]}
+    },
+    {
+      "type": "Feature",
+      "id": 7,
+      "properties": {},
+      "geometry": {"type": "Point", "coordinates": [-0.1246, 51.5007]}
+    },
+    {
+      "type": "Feature",
+      "properties": {"name": "Line"},
+      "geometry": {"type": "LineString", "coordinates": [[0, 0], [1, 1]]}
+    }
+  ]
+}"#;
+
+        let targets = read("targets.geojson", geojson).unwrap();
+
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].name, "Eiffel Tower");
+        assert_eq!(targets[0].coords, (48.8584, 2.2945));
+        assert_eq!(targets[1].name, "7");
+    }
+}
@@ -0,0 +1,298 @@
+use std::path::{Path, PathBuf};
+
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+
+use crate::{Error, Result};
+
+/// Extensions of the photos and videos searched by default
+pub const EXTENSIONS: [&str; 18] = [
+    "jpg", "jpeg", "mp4", "mov", "heic", "heif", "png", "webp", "tif", "tiff", "dng", "cr2", "cr3",
+    "nef", "arw", "raf", "orf", "rw2",
+];
+
+/// Gitignore-style files listing what not to search in their directory,
+/// e.g. `@eaDir` thumbnails on a NAS. `.gitignore` files are read as well.
+pub const IGNORE_FILE: &str = ".imnearignore";
+
+/// Finds photos under directories. Extensions and glob patterns are matched
+/// regardless of case, as cameras often write `IMG_0001.JPG`.
+#[derive(Debug, Clone)]
+pub struct Walker {
+    roots: Vec<PathBuf>,
+    extensions: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    hidden: bool,
+    ignore_files: bool,
+}
+
+impl Walker {
+    pub fn new<P: AsRef<Path>>(roots: &[P]) -> Walker {
+        Walker {
+            roots: roots
+                .iter()
+                .map(|root| root.as_ref().to_path_buf())
+                .collect(),
+            extensions: EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_depth: None,
+            follow_symlinks: false,
+            hidden: false,
+            ignore_files: true,
+        }
+    }
+
+    /// Only files with these extensions, with or without the leading dot
+    pub fn extensions<S: AsRef<str>>(mut self, extensions: &[S]) -> Walker {
+        self.extensions = extensions
+            .iter()
+            .map(|ext| ext.as_ref().trim_start_matches('.').to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Only files matching any of these gitignore-style globs, relative to
+    /// their root
+    pub fn include<S: AsRef<str>>(mut self, patterns: &[S]) -> Walker {
+        self.include = patterns.iter().map(|p| p.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Skip files and directories matching any of these globs, even if
+    /// included
+    pub fn exclude<S: AsRef<str>>(mut self, patterns: &[S]) -> Walker {
+        self.exclude = patterns.iter().map(|p| p.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Descend at most this many directories below a root, 1 for only the
+    /// files directly in it
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Walker {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Follow symbolic links to files and directories. Links back to a
+    /// parent directory are warned about and not followed again.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Walker {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Also search files and directories whose name starts with a dot
+    pub fn hidden(mut self, hidden: bool) -> Walker {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Skip what `.imnearignore` and `.gitignore` files list, which is the
+    /// default
+    pub fn ignore_files(mut self, ignore_files: bool) -> Walker {
+        self.ignore_files = ignore_files;
+        self
+    }
+
+    /// Paths of the roots one after the other, each sorted by name. Entries
+    /// that cannot be read, e.g. for lack of permission, come as errors
+    /// which the walk continues past.
+    pub fn paths(&self) -> Result<impl Iterator<Item = Result<String>>> {
+        let walks = self
+            .roots
+            .iter()
+            .map(|root| self.walk_builder(root).map(|builder| builder.build()))
+            .collect::<Result<Vec<_>>>()?;
+        let extensions = self.extensions.clone();
+        Ok(walks
+            .into_iter()
+            .flatten()
+            .filter_map(move |entry| match entry {
+                Ok(entry)
+                    if entry.file_type().is_some_and(|t| t.is_file())
+                        && has_extension(entry.path(), &extensions) =>
+                {
+                    Some(Ok(entry.into_path().to_string_lossy().to_string()))
+                }
+                Ok(_) => None,
+                Err(e) => Some(Err(Error::Walk(e.to_string()))),
+            }))
+    }
+
+    // Patterns are relative to each root, so each gets its own walk
+    fn walk_builder(&self, root: &Path) -> Result<WalkBuilder> {
+        let invalid = |e| Error::Walk(format!("Cannot search {}: {}", root.display(), e));
+        let mut patterns = OverrideBuilder::new(root);
+        patterns.case_insensitive(true).map_err(invalid)?;
+        for pattern in self.include.iter() {
+            patterns.add(pattern).map_err(invalid)?;
+        }
+        // Later patterns win, so excludes override includes
+        for pattern in self.exclude.iter() {
+            patterns.add(&format!("!{}", pattern)).map_err(invalid)?;
+        }
+        let patterns = patterns.build().map_err(invalid)?;
+
+        let mut builder = WalkBuilder::new(root);
+        builder
+            .standard_filters(false)
+            .hidden(!self.hidden)
+            .max_depth(self.max_depth)
+            .follow_links(self.follow_symlinks)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            // Not given as overrides, which would win over ignore files
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                !patterns.matched(entry.path(), is_dir).is_ignore()
+            });
+        if self.ignore_files {
+            // Photo folders are rarely git repositories
+            builder
+                .git_ignore(true)
+                .require_git(false)
+                .add_custom_ignore_filename(IGNORE_FILE);
+        }
+        Ok(builder)
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|ext| extensions.contains(&ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use tempfile::tempdir;
+
+    fn walk(walker: &Walker) -> Vec<String> {
+        walker
+            .paths()
+            .unwrap()
+            .filter_map(Result::ok)
+            .map(|path| {
+                Path::new(&path)
+                    .strip_prefix(&walker.roots[0])
+                    .unwrap_or(Path::new(&path))
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn walk_with_filters() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("2023/thumbs")).unwrap();
+        fs::create_dir_all(root.join("2024")).unwrap();
+        for path in [
+            "IMG_0001.JPG",
+            "clip.MP4",
+            "notes.txt",
+            "2023/a.jpeg",
+            "2023/thumbs/a.jpeg",
+            "2024/b.Heic",
+            "2024/c.mov",
+        ] {
+            fs::write(root.join(path), b"").unwrap();
+        }
+
+        let walker = Walker::new(&[root]);
+        assert_eq!(
+            walk(&walker),
+            [
+                "2023/a.jpeg",
+                "2023/thumbs/a.jpeg",
+                "2024/b.Heic",
+                "2024/c.mov",
+                "IMG_0001.JPG",
+                "clip.MP4"
+            ]
+        );
+
+        let walker = Walker::new(&[root]).extensions(&[".HEIC", "jpeg"]);
+        assert_eq!(
+            walk(&walker),
+            ["2023/a.jpeg", "2023/thumbs/a.jpeg", "2024/b.Heic"]
+        );
+
+        let walker = Walker::new(&[root])
+            .include(&["2023/**"])
+            .exclude(&["Thumbs"]);
+        assert_eq!(walk(&walker), ["2023/a.jpeg"]);
+
+        let walker = Walker::new(&[root]).exclude(&["*.mp4", "2024/"]);
+        assert_eq!(
+            walk(&walker),
+            ["2023/a.jpeg", "2023/thumbs/a.jpeg", "IMG_0001.JPG"]
+        );
+    }
+
+    #[test]
+    fn walk_with_options() {
+        let temp_dir = tempdir().unwrap();
+        let nas = temp_dir.path().join("nas");
+        let phone = temp_dir.path().join("phone");
+        for dir in ["@eaDir", ".thumbnails", "trip/day1"] {
+            fs::create_dir_all(nas.join(dir)).unwrap();
+        }
+        fs::create_dir_all(&phone).unwrap();
+        for path in [
+            "nas/a.jpg",
+            "nas/@eaDir/a.jpg",
+            "nas/.thumbnails/a.jpg",
+            "nas/.b.jpg",
+            "nas/trip/c.jpg",
+            "nas/trip/day1/d.jpg",
+            "nas/trip/skip.jpg",
+            "phone/e.jpg",
+        ] {
+            fs::write(temp_dir.path().join(path), b"").unwrap();
+        }
+        fs::write(nas.join(IGNORE_FILE), "@eaDir/\n").unwrap();
+        fs::write(nas.join("trip/.gitignore"), "skip.jpg\n").unwrap();
+
+        let walker = Walker::new(&[&nas, &phone]);
+        let found = walk(&walker);
+        assert_eq!(found[..3], ["a.jpg", "trip/c.jpg", "trip/day1/d.jpg"]);
+        assert!(found[3].ends_with("e.jpg"));
+
+        assert_eq!(
+            walk(&Walker::new(&[&nas]).max_depth(Some(2))),
+            ["a.jpg", "trip/c.jpg"]
+        );
+        assert_eq!(
+            walk(&Walker::new(&[&nas]).hidden(true).ignore_files(false)),
+            [
+                ".b.jpg",
+                ".thumbnails/a.jpg",
+                "@eaDir/a.jpg",
+                "a.jpg",
+                "trip/c.jpg",
+                "trip/day1/d.jpg",
+                "trip/skip.jpg"
+            ]
+        );
+
+        #[cfg(unix)]
+        {
+            // Loops back to the root are cut, while other links are followed
+            std::os::unix::fs::symlink(&nas, nas.join("trip/day1/loop")).unwrap();
+            std::os::unix::fs::symlink(&phone, nas.join("phone")).unwrap();
+            assert_eq!(walk(&Walker::new(&[&nas])).len(), 3);
+            let walker = Walker::new(&[&nas]).follow_symlinks(true);
+            assert_eq!(
+                walk(&walker),
+                ["a.jpg", "phone/e.jpg", "trip/c.jpg", "trip/day1/d.jpg"]
+            );
+            let errors = walker.paths().unwrap().filter(Result::is_err).count();
+            assert_eq!(errors, 1);
+        }
+    }
+}
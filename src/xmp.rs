@@ -0,0 +1,159 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const EXIF_NS: &str = "http://ns.adobe.com/exif/1.0/";
+
+/// Find `photo.ext.xmp` or `photo.xmp` next to the photo
+pub fn find_sidecar(path: &Path) -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    for ext in ["xmp", "XMP"] {
+        let mut appended = path.as_os_str().to_owned();
+        appended.push(".");
+        appended.push(ext);
+        candidates.push(PathBuf::from(appended));
+    }
+    for ext in ["xmp", "XMP"] {
+        candidates.push(path.with_extension(ext));
+    }
+
+    candidates
+        .into_iter()
+        .find(|candidate| candidate != path && candidate.is_file())
+}
+
+pub fn read_location(path: &Path) -> io::Result<Option<(f64, f64)>> {
+    let text = fs::read_to_string(path)?;
+    parse_location(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Read `exif:GPSLatitude` and `exif:GPSLongitude` in either attribute or
+/// element form
+pub fn parse_location(text: &str) -> Result<Option<(f64, f64)>, roxmltree::Error> {
+    let doc = roxmltree::Document::parse(text)?;
+    let find = |name: &str| {
+        doc.descendants().find_map(|node| {
+            if !node.is_element() {
+                return None;
+            }
+            if let Some(value) = node.attribute((EXIF_NS, name)) {
+                return Some(value.to_string());
+            }
+            let tag = node.tag_name();
+            if tag.namespace() == Some(EXIF_NS) && tag.name() == name {
+                return node.text().map(str::to_string);
+            }
+            None
+        })
+    };
+
+    let lat = find("GPSLatitude").and_then(|lat| parse_coordinate(&lat));
+    let lon = find("GPSLongitude").and_then(|lon| parse_coordinate(&lon));
+    match (lat, lon) {
+        (Some(lat), Some(lon)) => Ok(Some((lat, lon))),
+        _ => Ok(None),
+    }
+}
+
+/// Parse XMP GPS coordinate such as `40,26.767N` or `79,58,56W`
+pub fn parse_coordinate(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (body, sign) = match text.chars().last()? {
+        'N' | 'E' | 'n' | 'e' => (&text[..text.len() - 1], 1.0),
+        'S' | 'W' | 's' | 'w' => (&text[..text.len() - 1], -1.0),
+        _ => (text, 1.0),
+    };
+    let parts = body
+        .split(',')
+        .map(|part| part.trim().parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    let value = match parts[..] {
+        [deg] => deg,
+        [deg, min] => deg + min / 60.0,
+        [deg, min, sec] => deg + min / 60.0 + sec / 60.0 / 60.0,
+        _ => return None,
+    };
+    Some(sign * value)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    pub(crate) const SIDECAR_ATTRIBUTES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about=""
+        xmlns:exif="http://ns.adobe.com/exif/1.0/"
+        exif:GPSLatitude="40,26.767N"
+        exif:GPSLongitude="79,58.933W"/>
+  </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+    #[test]
+    fn parse_attributes() {
+        let (lat, lon) = parse_location(SIDECAR_ATTRIBUTES).unwrap().unwrap();
+
+        assert!((lat - 40.44611666).abs() < 1e-6);
+        assert!((lon + 79.98221666).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parse_elements() {
+        let text = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about="" xmlns:exif="http://ns.adobe.com/exif/1.0/">
+      <exif:GPSLatitude>33,51,24.48S</exif:GPSLatitude>
+      <exif:GPSLongitude>151,12,55.08E</exif:GPSLongitude>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+
+        let (lat, lon) = parse_location(text).unwrap().unwrap();
+
+        assert!((lat + 33.8568).abs() < 1e-6);
+        assert!((lon - 151.2153).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parse_no_location() {
+        let text = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"/>"#;
+
+        assert_eq!(parse_location(text).unwrap(), None);
+        assert!(parse_location("not xml").is_err());
+    }
+
+    #[test]
+    fn parse_coordinate_formats() {
+        assert_eq!(parse_coordinate("40,30N"), Some(40.5));
+        assert_eq!(parse_coordinate("40,30,36S"), Some(-40.51));
+        assert_eq!(parse_coordinate("-12.5"), Some(-12.5));
+        assert_eq!(parse_coordinate("1,2,3,4N"), None);
+        assert_eq!(parse_coordinate(""), None);
+    }
+
+    #[test]
+    fn find_sidecar_names() {
+        let temp_dir = tempdir().unwrap();
+        let photo = temp_dir.path().join("photo.jpg");
+        fs::write(&photo, b"").unwrap();
+
+        assert_eq!(find_sidecar(&photo), None);
+
+        fs::write(temp_dir.path().join("photo.xmp"), b"").unwrap();
+        assert_eq!(
+            find_sidecar(&photo),
+            Some(temp_dir.path().join("photo.xmp"))
+        );
+
+        fs::write(temp_dir.path().join("photo.jpg.xmp"), b"").unwrap();
+        assert_eq!(
+            find_sidecar(&photo),
+            Some(temp_dir.path().join("photo.jpg.xmp"))
+        );
+    }
+}